@@ -2,8 +2,8 @@ use std::fs;
 
 fn main() {
     let mut args = std::env::args().skip(1);
-    let a = fs::read(&args.next().unwrap()).unwrap();
-    let b = fs::read(&args.next().unwrap()).unwrap();
+    let a = fs::read(args.next().unwrap()).unwrap();
+    let b = fs::read(args.next().unwrap()).unwrap();
 
     for (i, (&a, &b)) in a.iter().zip(b.iter()).enumerate() {
         if a != b {
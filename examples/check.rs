@@ -1,6 +1,5 @@
-use fax::{decoder, decoder::pels, BitWriter, Bits, Color, VecWriter};
-use std::fs::{self, File};
-use std::io::Write;
+use fax::{decoder, decoder::pack_row, tiff, Polarity};
+use std::fs;
 
 fn split_once_byte(data: &[u8], needle: u8) -> Option<(&[u8], &[u8])> {
     let pos = data.iter().position(|&b| b == needle)?;
@@ -18,62 +17,14 @@ fn main() {
     let (header2, ref_image) = split_once_byte(data, b'\n').unwrap();
     let header2 = std::str::from_utf8(header2).unwrap();
     dbg!(header2);
-    let (w, h) = header2.split_once(" ").unwrap();
+    let (w, _h) = header2.split_once(" ").unwrap();
     let width: u32 = w.parse().unwrap();
-    let h: u32 = h.parse().unwrap();
 
     let mut ref_lines = ref_image.chunks_exact((width as usize + 7) / 8);
 
-    let data;
-    let inverted;
-    if input.ends_with(".tiff") {
-        use tiff::{decoder::Decoder, tags::Tag};
-        let tiff = std::fs::read(&input).unwrap();
-        let reader = std::io::Cursor::new(tiff.as_slice());
-        let mut decoder = Decoder::new(reader).unwrap();
-        let (w, h) = decoder.chunk_dimensions();
-        let mut buf = vec![0; w as usize * h as usize];
-        let strip_offset = decoder
-            .get_tag(Tag::StripOffsets)
-            .unwrap()
-            .into_u32()
-            .unwrap() as usize;
-        let strip_bytes = decoder
-            .get_tag(Tag::StripByteCounts)
-            .unwrap()
-            .into_u32()
-            .unwrap() as usize;
-
-        let interpr = decoder
-            .get_tag(Tag::PhotometricInterpretation)
-            .unwrap()
-            .into_u16()
-            .unwrap();
-
-        data = tiff[strip_offset..strip_offset + strip_bytes].to_vec();
-        inverted = interpr != 0;
-    } else {
-        data = fs::read(&input).unwrap();
-        inverted = false;
-    }
-    let mut height = 0;
-    let (black, white) = match inverted {
-        false => (Bits { data: 1, len: 1 }, Bits { data: 0, len: 1 }),
-        true => (Bits { data: 0, len: 1 }, Bits { data: 1, len: 1 }),
-    };
-    decoder::decode_g4(data.iter().cloned(), width, None, |transitions| {
-        let mut writer = VecWriter::new();
-        for c in pels(transitions, width) {
-            let bit = match c {
-                Color::Black => black,
-                Color::White => white,
-            };
-            writer.write(bit);
-        }
-        writer.pad();
-        let data = writer.finish();
+    let mut compare = |height: u32, data: &[u8]| {
         let ref_line = ref_lines.next().unwrap();
-        println!("{height:3} dec: {}", Line(&data));
+        println!("{height:3} dec: {}", Line(data));
         if ref_line != data {
             println!("    ref: {}", Line(ref_line));
             'a: for (byte, (&r, &v)) in ref_line.iter().zip(data.iter()).enumerate() {
@@ -88,8 +39,24 @@ fn main() {
             }
             panic!("decode error");
         }
-        height += 1;
-    });
+    };
+
+    if input.ends_with(".tiff") {
+        let pages = tiff::read(&fs::read(&input).unwrap()).unwrap();
+        let bitmap = pages[0].decode().unwrap();
+        for (height, row) in bitmap.rows().enumerate() {
+            compare(height as u32, row);
+        }
+    } else {
+        let data = fs::read(&input).unwrap();
+        let mut height = 0;
+        decoder::decode_g4(data.iter().cloned(), width, None, |transitions| {
+            let mut data = vec![0; (width as usize + 7) / 8];
+            pack_row(transitions, width, Polarity::WhiteIsZero, &mut data);
+            compare(height, &data);
+            height += 1;
+        });
+    }
 }
 
 struct Line<'a>(&'a [u8]);
@@ -1,4 +1,5 @@
-use fax::{decoder, decoder::pels, BitWriter, Bits, Color, VecWriter};
+use fax::bitmap::Bitmap;
+use fax::decoder;
 use std::fs::{self, File};
 use std::io::Write;
 
@@ -9,24 +10,24 @@ fn main() {
     let output = args.next().unwrap();
 
     let data = fs::read(&input).unwrap();
-    let mut writer = VecWriter::new();
-    let mut height = 0;
-    decoder::decode_g4(data.iter().cloned(), width, None, |transitions| {
-        for c in pels(transitions, width) {
-            let bit = match c {
-                Color::Black => Bits { data: 1, len: 1 },
-                Color::White => Bits { data: 0, len: 1 },
-            };
-            writer.write(bit);
-        }
-        writer.pad();
-        height += 1;
+    let mut bitmap = Bitmap::new(width, 0);
+    let complete = decoder::decode_g4(data.iter().cloned(), width, None, |transitions| {
+        bitmap.push_transitions(transitions)
     });
-    let data = writer.finish();
-    assert_eq!(data.len(), height as usize * ((width as usize + 7) / 8));
+    if complete.is_none() {
+        eprintln!(
+            "{}: damaged after {} lines, writing what was decoded",
+            input,
+            bitmap.height()
+        );
+    }
 
-    let header = format!("P4\n{} {}\n", width, height);
+    let header = format!("P4\n{} {}\n", bitmap.width(), bitmap.height());
     let mut out = File::create(&output).unwrap();
     out.write_all(header.as_bytes()).unwrap();
-    out.write_all(&data).unwrap();
+    out.write_all(bitmap.data()).unwrap();
+
+    if complete.is_none() {
+        std::process::exit(1);
+    }
 }
@@ -1,7 +1,6 @@
+use fax::decoder;
 use fax::tiff::wrap;
-use fax::{decoder, decoder::pels, BitWriter, Bits, Color, VecWriter};
-use std::fs::{self, File};
-use std::io::Write;
+use std::fs;
 
 fn main() {
     let mut args = std::env::args().skip(1);
@@ -11,7 +10,7 @@ fn main() {
 
     let data = fs::read(&input).unwrap();
     let mut height = 0;
-    decoder::decode_g4(data.iter().cloned(), width, None, |transitions| {
+    decoder::decode_g4(data.iter().cloned(), width, None, |_| {
         height += 1;
     });
 
@@ -1,4 +1,5 @@
-use fax::{encoder::Encoder, slice_bits, tiff, ByteReader, Color, VecWriter};
+use fax::bitmap::Bitmap;
+use fax::tiff::{Compression, Page, TiffWriter};
 use std::fs;
 
 fn main() {
@@ -19,22 +20,25 @@ fn main() {
         .parse()
         .unwrap();
 
-    let writer = VecWriter::new();
-    let mut encoder = Encoder::new(writer);
-
-    for line in parts
-        .next()
-        .unwrap()
-        .chunks((width as usize + 7) / 8)
-        .take(height as _)
-    {
-        let line = slice_bits(line).take(width as usize).map(|b| match b {
-            false => Color::White,
-            true => Color::Black,
-        });
-        encoder.encode_line(line, width).unwrap();
+    let stride = (width as usize + 7) / 8;
+    let mut rows = parts.next().unwrap().to_vec();
+    rows.truncate(stride * height as usize);
+    if rows.len() < stride * height as usize {
+        eprintln!(
+            "{}: expected {} bytes of pixel data for {}x{}, found {}",
+            input,
+            stride * height as usize,
+            width,
+            height,
+            rows.len()
+        );
+        std::process::exit(1);
     }
-    let data = encoder.finish().unwrap().finish();
+    let bitmap = Bitmap::from_data(width, height, rows).unwrap();
 
-    fs::write(&output, &tiff::wrap(&data, width, height)).unwrap();
+    // strips of 128 rows, so that viewers can decode parts of the page
+    let page = Page::encode(&bitmap, Compression::Group4 { t6_options: 0 }, 128);
+    let mut writer = TiffWriter::new(Vec::new()).with_page_count(1);
+    writer.write_page(&page).unwrap();
+    fs::write(&output, writer.finish().unwrap()).unwrap();
 }
@@ -0,0 +1,15 @@
+use fax::{pdf::PdfWriter, tiff};
+use std::fs;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let input: String = args.next().unwrap();
+    let output = args.next().unwrap();
+
+    let pages = tiff::read(&fs::read(&input).unwrap()).unwrap();
+    let mut writer = PdfWriter::new(Vec::new());
+    for page in &pages {
+        writer.write_tiff_page(page).unwrap();
+    }
+    fs::write(&output, writer.finish().unwrap()).unwrap();
+}
@@ -1,7 +1,7 @@
 use fax::{
-    encoder::Encoder, slice_bits, slice_reader, BitReader, BitWriter, Bits, ByteReader, Color,
+    encoder::Encoder, slice_pels, slice_reader, BitReader, BitWriter, Bits, ByteReader, Polarity,
 };
-use std::{convert::Infallible, fs};
+use std::fs;
 
 fn main() {
     let mut args = std::env::args().skip(1);
@@ -36,10 +36,7 @@ fn main() {
         .take(height as _)
     {
         println!("\nline {}", y);
-        let line = slice_bits(line).take(width as usize).map(|b| match b {
-            false => Color::Black,
-            true => Color::White,
-        });
+        let line = slice_pels(line, width, Polarity::BlackIsZero);
         encoder.encode_line(line, width).unwrap();
     }
     let mut writer = encoder.finish().unwrap();
@@ -66,7 +63,6 @@ where
             println!("{} != {}", expected, bits);
             return Err(());
         }
-        self.reader.consume(bits.len);
-        Ok(())
+        self.reader.consume(bits.len).map_err(|_| ())
     }
 }
@@ -0,0 +1,183 @@
+use crate::decoder::pack_row;
+use crate::{slice_pels, Color, Polarity};
+
+/// A bilevel image held in packed rows.
+///
+/// Pixels are stored MSB-first with `1` for black (like PBM),
+/// every row is padded to a whole byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+impl Bitmap {
+    /// Create an all-white image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Bitmap {
+            width,
+            height,
+            data: vec![0; stride(width) * height as usize],
+        }
+    }
+    /// Wrap packed rows of `(width + 7) / 8` bytes each.
+    ///
+    /// Returns `None` if `data` does not hold exactly `height` rows.
+    pub fn from_data(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        if data.len() != stride(width) * height as usize {
+            return None;
+        }
+        Some(Bitmap {
+            width,
+            height,
+            data,
+        })
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// Number of bytes per row.
+    pub fn stride(&self) -> usize {
+        stride(self.width)
+    }
+    /// All rows, one after the other.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn row(&self, y: u32) -> &[u8] {
+        let stride = self.stride();
+        let start = y as usize * stride;
+        &self.data[start..start + stride]
+    }
+    pub fn row_mut(&mut self, y: u32) -> &mut [u8] {
+        let stride = self.stride();
+        let start = y as usize * stride;
+        &mut self.data[start..start + stride]
+    }
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        (0..self.height).map(move |y| self.row(y))
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        assert!(x < self.width);
+        let byte = self.row(y)[x as usize / 8];
+        Polarity::WhiteIsZero.color(byte & (0x80 >> (x % 8)) != 0)
+    }
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        assert!(x < self.width);
+        let byte = &mut self.row_mut(y)[x as usize / 8];
+        let mask = 0x80 >> (x % 8);
+        if Polarity::WhiteIsZero.bit(color) {
+            *byte |= mask;
+        } else {
+            *byte &= !mask;
+        }
+    }
+
+    /// The pixels of row `y`.
+    pub fn pels(&self, y: u32) -> impl Iterator<Item = Color> + '_ {
+        slice_pels(self.row(y), self.width, Polarity::WhiteIsZero)
+    }
+    /// Replace row `y` by the given pixels, missing ones are white.
+    pub fn set_pels(&mut self, y: u32, pels: impl Iterator<Item = Color>) {
+        let width = self.width as usize;
+        let row = self.row_mut(y);
+        row.fill(0);
+        for (i, c) in pels.take(width).enumerate() {
+            if Polarity::WhiteIsZero.bit(c) {
+                row[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+    }
+    /// The color changes in row `y`, starting with white.
+    pub fn transitions(&self, y: u32) -> Vec<u32> {
+        let mut color = Color::White;
+        let mut out = Vec::new();
+        for (i, c) in self.pels(y).enumerate() {
+            if c != color {
+                out.push(i as u32);
+                color = c;
+            }
+        }
+        out
+    }
+    /// Replace row `y` by the line described by `transitions`.
+    pub fn set_transitions(&mut self, y: u32, transitions: &[u32]) {
+        let width = self.width;
+        pack_row(transitions, width, Polarity::WhiteIsZero, self.row_mut(y));
+    }
+    /// Append a row described by `transitions`.
+    ///
+    /// Suitable as the line callback of the decode functions.
+    pub fn push_transitions(&mut self, transitions: &[u32]) {
+        self.data.resize(self.data.len() + self.stride(), 0);
+        self.height += 1;
+        self.set_transitions(self.height - 1, transitions);
+    }
+    /// Append a row of pixels, missing ones are white.
+    pub fn push_pels(&mut self, pels: impl Iterator<Item = Color>) {
+        self.data.resize(self.data.len() + self.stride(), 0);
+        self.height += 1;
+        self.set_pels(self.height - 1, pels);
+    }
+}
+
+fn stride(width: u32) -> usize {
+    (width as usize + 7) / 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::pels;
+
+    #[test]
+    fn pixels_and_transitions() {
+        let mut bitmap = Bitmap::new(10, 2);
+        assert_eq!(bitmap.stride(), 2);
+        bitmap.set_transitions(0, &[2, 5, 9]);
+        assert_eq!(bitmap.row(0), &[0b0011_1000, 0b0100_0000]);
+        assert_eq!(bitmap.transitions(0), vec![2, 5, 9]);
+        assert_eq!(bitmap.get(2, 0), Color::Black);
+        assert_eq!(bitmap.get(5, 0), Color::White);
+
+        bitmap.set(9, 0, Color::White);
+        bitmap.set(0, 1, Color::Black);
+        assert_eq!(bitmap.transitions(0), vec![2, 5]);
+        assert_eq!(bitmap.transitions(1), vec![0, 1]);
+        assert_eq!(
+            bitmap.pels(1).collect::<Vec<_>>(),
+            pels(&[0, 1], 10).collect::<Vec<_>>()
+        );
+
+        bitmap.push_pels(pels(&[0], 10));
+        assert_eq!(bitmap.height(), 3);
+        assert_eq!(bitmap.row(2), &[0xff, 0xc0]);
+        assert_eq!(bitmap.rows().count(), 3);
+
+        let data = bitmap.clone().into_data();
+        assert_eq!(Bitmap::from_data(10, 3, data.clone()), Some(bitmap));
+        assert_eq!(Bitmap::from_data(10, 2, data), None);
+    }
+
+    #[test]
+    fn g4_roundtrip() {
+        let mut bitmap = Bitmap::new(20, 3);
+        bitmap.set_transitions(0, &[3, 7]);
+        bitmap.set_transitions(2, &[0, 19]);
+
+        let mut encoder = crate::encoder::Encoder::new(crate::VecWriter::new());
+        encoder.encode_bitmap(&bitmap).unwrap();
+        let data = encoder.finish().unwrap().finish();
+
+        let decoded = crate::decoder::decode_g4_bitmap(data.into_iter(), 20, Some(3));
+        assert_eq!(decoded, Some(bitmap));
+    }
+}
@@ -1,8 +1,11 @@
 use std::convert::Infallible;
-use std::io::{self, Bytes, Read};
 
-use crate::maps::{black, mode, white, Mode, EDFB_HALF, EOL};
-use crate::{BitReader, ByteReader, Color, Transitions};
+use crate::bitmap::Bitmap;
+use crate::lookup;
+use crate::maps::Mode;
+use crate::pdf::CcittParams;
+use crate::{BitReader, ByteReader, Color, FillOrder, Polarity, ReadBytes, Transitions};
+use std::io;
 
 fn with_markup<D, R>(decoder: D, reader: &mut R) -> Option<u32>
 where
@@ -23,8 +26,8 @@ where
 fn colored(current: Color, reader: &mut impl BitReader) -> Option<u32> {
     //debug!("{:?}", current);
     match current {
-        Color::Black => with_markup(black::decode, reader),
-        Color::White => with_markup(white::decode, reader),
+        Color::Black => with_markup(lookup::black, reader),
+        Color::White => with_markup(lookup::white, reader),
     }
 }
 
@@ -49,16 +52,92 @@ pub fn pels(line: &[u32], width: u32) -> impl Iterator<Item = Color> + '_ {
         .take(width as usize)
 }
 
+/// Write the pixels of a line into a packed row, with bits set according to `polarity`.
+///
+/// `out` has to hold at least `(width + 7) / 8` bytes.
+/// Bits after `width` in the last byte are set to `0`.
+///
+/// Runs are written a byte range at a time, which is much faster than going through `pels`.
+pub fn pack_row(line: &[u32], width: u32, polarity: Polarity, out: &mut [u8]) {
+    let out = &mut out[..(width as usize + 7) / 8];
+    let black = polarity.bit(Color::Black);
+    out.fill(if black { 0 } else { 0xff });
+
+    let mut last = 0;
+    for run in line.chunks(2) {
+        let start = run[0].clamp(last, width);
+        let end = run.get(1).map_or(width, |&end| end.clamp(start, width));
+        if start < end {
+            fill_bits(out, start as usize, end as usize, black);
+        }
+        last = end;
+    }
+
+    if width % 8 != 0 {
+        if let Some(byte) = out.last_mut() {
+            *byte &= 0xff << (8 - width % 8);
+        }
+    }
+}
+
+/// Set the bits `start .. end` of a packed row to `bit`.
+fn fill_bits(out: &mut [u8], start: usize, end: usize, bit: bool) {
+    fn apply(byte: &mut u8, mask: u8, bit: bool) {
+        if bit {
+            *byte |= mask;
+        } else {
+            *byte &= !mask;
+        }
+    }
+    let first = start / 8;
+    let last = (end - 1) / 8;
+    let head = 0xff >> (start % 8);
+    let tail = 0xff << (7 - (end - 1) % 8);
+    if first == last {
+        apply(&mut out[first], head & tail, bit);
+    } else {
+        apply(&mut out[first], head, bit);
+        out[first + 1..last].fill(if bit { 0xff } else { 0 });
+        apply(&mut out[last], tail, bit);
+    }
+}
+
 /// Decode a Group 3 encoded image.
 ///
 /// The callback `line_cb` is called for each decoded line.
 /// The argument is the list of positions of color change, starting with white.
 ///
 /// To obtain an iterator over the pixel colors, the `pels` function is provided.
-pub fn decode_g3(input: impl Iterator<Item = u8>, mut line_cb: impl FnMut(&[u32])) -> Option<()> {
+///
+/// Only one-dimensional (Modified Huffman) streams are handled here,
+/// use `decode_g3_2d` for streams that contain two-dimensional lines.
+pub fn decode_g3(input: impl Iterator<Item = u8>, line_cb: impl FnMut(&[u32])) -> Option<()> {
+    let reader = input.map(Result::<u8, Infallible>::Ok);
+    let decoder = Group3Decoder::new(reader).ok()?;
+    run_g3(decoder, line_cb)
+}
+
+/// Decode a two-dimensional Group 3 encoded image (Modified READ, `K > 0`).
+///
+/// Every EOL in the stream has to be followed by a tag bit that selects
+/// one-dimensional (`1`) or two-dimensional (`0`) coding for the next line.
+/// `width` is the width of the image.
+///
+/// The callback `line_cb` is called for each decoded line, like in `decode_g3`.
+pub fn decode_g3_2d(
+    input: impl Iterator<Item = u8>,
+    width: u32,
+    line_cb: impl FnMut(&[u32]),
+) -> Option<()> {
     let reader = input.map(Result::<u8, Infallible>::Ok);
-    let mut decoder = Group3Decoder::new(reader).ok()?;
+    let decoder = Group3Decoder::new_2d(reader, width).ok()?;
+    run_g3(decoder, line_cb)
+}
 
+fn run_g3<E: std::fmt::Debug, R: Iterator<Item = Result<u8, E>>>(
+    mut decoder: Group3Decoder<R>,
+    mut line_cb: impl FnMut(&[u32]),
+) -> Option<()> {
     while let Ok(status) = decoder.advance() {
         // Always emit the decoded line before checking for end-of-document.
         // The last line before the RTC (Return To Control) marker contains
@@ -79,46 +158,259 @@ pub enum DecodeStatus {
 
 pub struct Group3Decoder<R> {
     reader: ByteReader<R>,
+    reference: Vec<u32>,
     current: Vec<u32>,
+    /// Width of the image, if known. Lines end when it is reached.
+    width: Option<u32>,
+    /// A tag bit selecting 1D or 2D coding precedes every line.
+    two_dimensional: bool,
+    /// Every line has to be terminated by an EOL.
+    eol_required: bool,
+    /// Lines without EOL start at a byte boundary.
+    byte_align: bool,
+    /// Coding of the next line, as announced by the last tag bit.
+    next_2d: bool,
+    /// The stream has to start with an EOL.
+    initial_eol: bool,
+    /// The start of the stream has been read.
+    started: bool,
+    /// Six EOLs (RTC) end the data.
+    end_of_block: bool,
+    /// Number of consecutive damaged lines to conceal, `None` to fail on the first one.
+    max_damaged_rows: Option<u32>,
+    damaged_rows: u32,
+    consecutive_damaged_rows: u32,
+    /// The reference line is a concealed one, so 2D lines can't be decoded.
+    concealing: bool,
 }
 impl<E: std::fmt::Debug, R: Iterator<Item = Result<u8, E>>> Group3Decoder<R> {
+    /// Construct a decoder for a one-dimensional stream.
+    ///
+    /// Fails if the data does not start with an EOL, possibly after fill bits.
     pub fn new(reader: R) -> Result<Self, DecodeError<E>> {
-        let mut reader = ByteReader::new(reader).map_err(DecodeError::Reader)?;
-        // Skip any fill bits (zeros) then consume the initial EOL marker.
-        skip_to_eol(&mut reader).map_err(|_| DecodeError::Invalid)?;
+        let decoder = Self::with_width(reader, None)?;
+        // The EOL is read with the first line, once the fill order is known.
+        // Until then, check for the `1` that ends it, which is found in either order.
+        if !decoder.reader.any_set() {
+            return Err(DecodeError::Invalid);
+        }
+        Ok(decoder)
+    }
+    /// Construct a decoder for a two-dimensional (Modified READ) stream of the given width.
+    pub fn new_2d(reader: R, width: u32) -> Result<Self, DecodeError<E>> {
+        Self::with_width(reader, Some(width))
+    }
+    fn with_width(reader: R, width: Option<u32>) -> Result<Self, DecodeError<E>> {
+        let reader = ByteReader::new(reader).map_err(DecodeError::Reader)?;
 
         Ok(Group3Decoder {
             reader,
+            reference: vec![],
             current: vec![],
+            width,
+            two_dimensional: width.is_some(),
+            eol_required: true,
+            byte_align: false,
+            next_2d: false,
+            initial_eol: true,
+            started: false,
+            end_of_block: true,
+            max_damaged_rows: None,
+            damaged_rows: 0,
+            consecutive_damaged_rows: 0,
+            concealing: false,
+        })
+    }
+    /// Construct a decoder for a stream described by PDF `CCITTFaxDecode` parameters.
+    ///
+    /// `params.k` has to be `>= 0`, `K > 0` selects two-dimensional coding.
+    /// EOLs are optional unless `params.end_of_line` is set, lines always end
+    /// after `params.columns` pixels. Unless `params.end_of_block` is set, an RTC
+    /// is not recognized and the caller has to stop after `params.rows` lines.
+    pub fn with_params(reader: R, params: &CcittParams) -> Result<Self, DecodeError<E>> {
+        let decoder = Self::with_width(reader, Some(params.columns))?;
+        Ok(Group3Decoder {
+            two_dimensional: params.k > 0,
+            eol_required: params.end_of_line,
+            byte_align: params.encoded_byte_align,
+            initial_eol: false,
+            end_of_block: params.end_of_block,
+            ..decoder
         })
     }
+    /// Set the order of bits within each byte of the input.
+    ///
+    /// Has to be called before the first line is decoded.
+    pub fn with_fill_order(mut self, fill_order: FillOrder) -> Self {
+        self.reader.set_fill_order(fill_order);
+        self
+    }
+    /// Skip any fill bits (zeros) then consume the initial EOL marker.
+    fn start(&mut self) -> Result<(), DecodeError<E>> {
+        if self.initial_eol || is_eol_ahead(&self.reader) {
+            skip_to_eol(&mut self.reader).map_err(|_| DecodeError::Invalid)?;
+        }
+        self.read_tag()
+    }
+    /// Read the 1D/2D tag bit preceding a line, if the stream has them.
+    fn read_tag(&mut self) -> Result<(), DecodeError<E>> {
+        if self.two_dimensional {
+            let tag = self.reader.peek(1).ok_or(DecodeError::Invalid)?;
+            self.reader.consume(1).map_err(DecodeError::Reader)?;
+            self.next_2d = tag == 0;
+        }
+        Ok(())
+    }
+    /// Conceal damaged lines instead of failing.
+    ///
+    /// When a line can't be decoded, the decoder skips to the next EOL and
+    /// repeats the previous line in its place (T.4 error concealment).
+    /// Two-dimensional lines that follow are repeated as well, up to the next
+    /// one-dimensional line. They are counted, but not towards the limit.
+    /// Decoding only fails after more than `max_damaged_rows` consecutive damaged lines,
+    /// like `/DamagedRowsBeforeError` in PDF. This requires EOLs in the stream.
+    pub fn with_recovery(mut self, max_damaged_rows: u32) -> Self {
+        self.max_damaged_rows = Some(max_damaged_rows);
+        self
+    }
+    /// Number of damaged lines that were concealed so far.
+    pub fn damaged_rows(&self) -> u32 {
+        self.damaged_rows
+    }
     pub fn advance(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        if !self.started {
+            self.started = true;
+            if let Err(e) = self.start() {
+                return with_read_error(&mut self.reader, Err(e));
+            }
+        }
+        std::mem::swap(&mut self.reference, &mut self.current);
         self.current.clear();
-        let mut a0: u32 = 0;
-        let mut color = Color::White;
-        loop {
-            // Check for EOL before attempting to parse a run-length code.
-            // This prevents the prefix tree from destructively consuming
-            // EOL bits that it can't match as a valid code.
-            if is_eol_ahead(&self.reader) {
-                break;
+
+        if self.concealing && self.next_2d {
+            // the line refers to a concealed one
+            self.damaged_rows += 1;
+            let result = self.conceal();
+            return with_read_error(&mut self.reader, result);
+        }
+        let result = match self.decode_line().and_then(|()| self.end_line()) {
+            Ok(status) => {
+                self.consecutive_damaged_rows = 0;
+                self.concealing = false;
+                Ok(status)
             }
-            match colored(color, &mut self.reader) {
-                Some(p) => {
-                    a0 = a0.checked_add(p).ok_or(DecodeError::Invalid)?;
-                    self.current.push(a0);
-                    color = !color;
+            Err(e @ (DecodeError::Invalid | DecodeError::Unsupported)) => {
+                match self.max_damaged_rows {
+                    Some(max) if self.consecutive_damaged_rows < max => self.recover(),
+                    _ => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        with_read_error(&mut self.reader, result)
+    }
+    /// Replace the damaged line by the previous one and resynchronise at the next EOL.
+    fn recover(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        self.damaged_rows += 1;
+        self.consecutive_damaged_rows += 1;
+        self.concealing = true;
+        self.conceal()
+    }
+    /// Repeat the previous line and skip to the next EOL.
+    fn conceal(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        self.current.clear();
+        self.current.extend_from_slice(&self.reference);
+
+        while !is_eol_ahead(&self.reader) {
+            if self.reader.peek(1).is_none() {
+                return Ok(DecodeStatus::End);
+            }
+            self.reader.consume(1).map_err(DecodeError::Reader)?;
+        }
+        self.end_line()
+    }
+    /// Decode the pixels of one line into `self.current`.
+    fn decode_line(&mut self) -> Result<(), DecodeError<E>> {
+        match self.width {
+            Some(width) if self.next_2d => {
+                decode_2d_line(
+                    &mut self.reader,
+                    &self.reference,
+                    &mut self.current,
+                    width,
+                    true,
+                )?;
+            }
+            width => {
+                let mut a0: u32 = 0;
+                let mut color = Color::White;
+                loop {
+                    // Check for EOL before attempting to parse a run-length code.
+                    // This prevents the prefix tree from destructively consuming
+                    // EOL bits that it can't match as a valid code.
+                    if is_eol_ahead(&self.reader) {
+                        break;
+                    }
+                    match (colored(color, &mut self.reader), width) {
+                        (Some(p), _) => {
+                            a0 = a0.checked_add(p).ok_or(DecodeError::Invalid)?;
+                            self.current.push(a0);
+                            color = !color;
+                        }
+                        (None, None) => break,
+                        (None, Some(_)) => return Err(DecodeError::Invalid),
+                    }
+                    if width.is_some_and(|width| a0 >= width) {
+                        break;
+                    }
+                }
+                // Lines that serve as reference for 2D coding use the same
+                // canonical form as the Group 4 decoder: no entry at `width`.
+                if let Some(width) = width {
+                    while self.current.last().is_some_and(|&t| t >= width) {
+                        self.current.pop();
+                    }
                 }
-                None => break,
             }
         }
-        // Skip any fill bits and consume the EOL.
-        skip_to_eol(&mut self.reader).map_err(|_| DecodeError::Invalid)?;
+
+        Ok(())
+    }
+    /// Consume the EOL (or alignment) after a line and look for the end of the document.
+    fn end_line(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        // the data may end without an RTC
+        if at_end(&self.reader) {
+            return Ok(DecodeStatus::End);
+        }
+        let eol = is_eol_ahead(&self.reader);
+        if !eol && self.eol_required {
+            return Err(DecodeError::Invalid);
+        }
+        if eol {
+            // Skip any fill bits and consume the EOL.
+            skip_to_eol(&mut self.reader).map_err(|_| DecodeError::Invalid)?;
+            if at_end(&self.reader) {
+                return Ok(DecodeStatus::End);
+            }
+        } else {
+            if self.byte_align {
+                let fill = self.reader.bits_to_byte_boundary();
+                self.reader.consume(fill).map_err(DecodeError::Reader)?;
+            }
+            if self.reader.peek(1).is_none() {
+                return Ok(DecodeStatus::End);
+            }
+        }
+        self.read_tag()?;
+        if !eol || !self.end_of_block {
+            return Ok(DecodeStatus::Incomplete);
+        }
 
         // Check for end-of-document: 6 consecutive EOLs (5 more after the one above).
         for _ in 0..5 {
             if is_eol_ahead(&self.reader) {
                 skip_to_eol(&mut self.reader).map_err(|_| DecodeError::Invalid)?;
+                self.read_tag()?;
             } else {
                 return Ok(DecodeStatus::Incomplete);
             }
@@ -130,6 +422,31 @@ impl<E: std::fmt::Debug, R: Iterator<Item = Result<u8, E>>> Group3Decoder<R> {
         &self.current
     }
 }
+impl<R: io::Read> Group3Decoder<ReadBytes<R>> {
+    /// Construct a decoder reading a one-dimensional stream from `read`.
+    ///
+    /// For the other modes, pass `ReadBytes::new(read)` to the respective constructor.
+    pub fn from_read(read: R) -> Result<Self, DecodeError<io::Error>> {
+        Self::new(ReadBytes::new(read))
+    }
+}
+
+/// Blame a read error for the decoder stopping, rather than the data.
+///
+/// Without more input, the data looks truncated or invalid. Read errors inside
+/// the code tables are lost, so the read is tried again.
+fn with_read_error<E, R: Iterator<Item = Result<u8, E>>>(
+    reader: &mut ByteReader<R>,
+    result: Result<DecodeStatus, DecodeError<E>>,
+) -> Result<DecodeStatus, DecodeError<E>> {
+    match result {
+        Ok(DecodeStatus::Incomplete) | Err(DecodeError::Reader(_)) => result,
+        _ => match reader.fill() {
+            Err(e) => Err(DecodeError::Reader(e)),
+            Ok(()) => result,
+        },
+    }
+}
 
 /// Check if the next bits form an EOL marker (possibly with fill bits).
 ///
@@ -140,18 +457,22 @@ impl<E: std::fmt::Debug, R: Iterator<Item = Result<u8, E>>> Group3Decoder<R> {
 /// We peek at 9 bits: if all zero, this is definitely fill+EOL or bare EOL
 /// (the EOL itself starts with 11 zeros). This handles any fill count
 /// without exceeding the 16-bit peek window.
-fn is_eol_ahead<E, R: Iterator<Item = Result<u8, E>>>(reader: &ByteReader<R>) -> bool {
+fn is_eol_ahead(reader: &impl BitReader) -> bool {
     // 9 zero bits cannot be the start of any valid run-length code
     // (max leading zeros in any code is 7). Must be fill + EOL.
     // This also matches bare EOL (000000000001) since its first 9 bits are zero.
     reader.peek(9) == Some(0)
 }
 
+/// Only zero bits up to the end of the last byte are left.
+fn at_end(reader: &impl BitReader) -> bool {
+    let fill = reader.bits_to_byte_boundary();
+    reader.peek(fill + 1).is_none() && reader.peek(fill) == Some(0)
+}
+
 /// Skip zero fill bits and consume the EOL marker (000000000001).
 /// Returns Err if no valid EOL is found.
-fn skip_to_eol<E: std::fmt::Debug, R: Iterator<Item = Result<u8, E>>>(
-    reader: &mut ByteReader<R>,
-) -> Result<(), DecodeError<E>> {
+fn skip_to_eol<R: BitReader>(reader: &mut R) -> Result<(), DecodeError<R::Error>> {
     // Skip zero fill bits (used for byte alignment in Group3Options bit 2).
     while reader.peek(1) == Some(0) {
         reader.consume(1).map_err(DecodeError::Reader)?;
@@ -165,6 +486,188 @@ fn skip_to_eol<E: std::fmt::Debug, R: Iterator<Item = Result<u8, E>>>(
     }
 }
 
+/// Decode one two-dimensionally coded line against `reference` into `current`.
+///
+/// This is shared by the Group 4 decoder and the 2D lines of Group 3.
+/// With `stop_at_eol` the line also ends when an EOL is ahead, without consuming it.
+fn decode_2d_line<R: BitReader>(
+    reader: &mut R,
+    reference: &[u32],
+    current: &mut Vec<u32>,
+    width: u32,
+    stop_at_eol: bool,
+) -> Result<DecodeStatus, DecodeError<R::Error>> {
+    let mut transitions = Transitions::new(reference);
+    let mut a0 = 0;
+    let mut color = Color::White;
+    let mut start_of_row = true;
+
+    loop {
+        if stop_at_eol && is_eol_ahead(reader) {
+            break;
+        }
+        //reader.print_peek();
+        let mode = match lookup::mode(reader) {
+            Some(mode) => mode,
+            None => return Err(DecodeError::Invalid),
+        };
+        //debug!("  {:?}, color={:?}, a0={}", mode, color, a0);
+
+        match mode {
+            Mode::Pass => {
+                if start_of_row && color == Color::White {
+                    transitions.pos += 1;
+                } else {
+                    transitions
+                        .next_color(a0, !color, false)
+                        .ok_or(DecodeError::Invalid)?;
+                }
+                //debug!("b1={}", b1);
+                if let Some(b2) = transitions.next() {
+                    //debug!("b2={}", b2);
+                    a0 = b2;
+                }
+            }
+            Mode::Vertical(delta) => {
+                let b1 = transitions
+                    .next_color(a0, !color, start_of_row)
+                    .unwrap_or(width);
+                let a1_i32 = b1 as i32 + delta as i32;
+                if a1_i32 < 0 || a1_i32 > width as i32 {
+                    break;
+                }
+                let a1 = a1_i32 as u32;
+                //debug!("transition to {:?} at {}", !color, a1);
+                // Canonical form: only store transitions strictly less
+                // than width. A transition at width is the implicit
+                // end-of-line and is not a color change. This matches
+                // the encoder's `self.current` representation (see
+                // encoder.rs — it only pushes values yielded by pels,
+                // which are always in [0, width-1]).
+                if a1 < width {
+                    current.push(a1);
+                }
+                color = !color;
+                a0 = a1;
+                if delta < 0 {
+                    transitions.seek_back(a0);
+                }
+            }
+            Mode::Horizontal => {
+                let a0a1 = colored(color, reader).ok_or(DecodeError::Invalid)?;
+                let a1a2 = colored(!color, reader).ok_or(DecodeError::Invalid)?;
+                let a1 = a0.checked_add(a0a1).ok_or(DecodeError::Invalid)?;
+                let a2 = a1.checked_add(a1a2).ok_or(DecodeError::Invalid)?;
+                //debug!("a0a1={}, a1a2={}, a1={}, a2={}", a0a1, a1a2, a1, a2);
+
+                // Same canonical form rule: never store a transition
+                // at width (it's the end-of-line sentinel, not a flip).
+                if a1 < width {
+                    current.push(a1);
+                }
+                if a2 >= width {
+                    break;
+                }
+                current.push(a2);
+                a0 = a2;
+            }
+            Mode::Extension => {
+                let ext = reader.peek(3).ok_or(DecodeError::Invalid)?;
+                reader.consume(3).map_err(DecodeError::Reader)?;
+                if ext != 0b111 {
+                    return Err(DecodeError::Unsupported);
+                }
+                let (a1, next) = uncompressed(reader, current, a0, color, width)?;
+                a0 = a1;
+                color = next;
+            }
+            Mode::EOF => return Ok(DecodeStatus::End),
+        }
+        start_of_row = false;
+
+        if a0 >= width {
+            break;
+        }
+    }
+    //debug!("{:?}", current);
+
+    Ok(DecodeStatus::Incomplete)
+}
+
+/// Decode pixels in uncompressed mode (extension `111`), starting at `a0` with `color`.
+///
+/// Returns the position and color of the next run once the exit code was read.
+fn uncompressed<R: BitReader>(
+    reader: &mut R,
+    current: &mut Vec<u32>,
+    mut a0: u32,
+    mut color: Color,
+    width: u32,
+) -> Result<(u32, Color), DecodeError<R::Error>> {
+    // Toggle the color at `pos`. A change at the position of the
+    // previous change cancels it out.
+    fn flip(current: &mut Vec<u32>, pos: u32, width: u32) {
+        if pos >= width {
+            return;
+        }
+        if current.last() == Some(&pos) {
+            current.pop();
+        } else {
+            current.push(pos);
+        }
+    }
+    let mut pixels = |current: &mut Vec<u32>, c: Color, n: u32| {
+        if n == 0 {
+            return Ok(());
+        }
+        if n > width - a0 {
+            return Err(DecodeError::Invalid);
+        }
+        if c != color {
+            flip(current, a0, width);
+            color = c;
+        }
+        a0 += n;
+        Ok(())
+    };
+
+    loop {
+        // Every code is a number of zeros terminated by a one.
+        let mut zeros = 0;
+        loop {
+            let bit = reader.peek(1).ok_or(DecodeError::Invalid)?;
+            reader.consume(1).map_err(DecodeError::Reader)?;
+            if bit == 1 {
+                break;
+            }
+            zeros += 1;
+            if zeros > 10 {
+                return Err(DecodeError::Invalid);
+            }
+        }
+        match zeros {
+            // `0..01`: up to four white pixels followed by one black pixel
+            0..=4 => {
+                pixels(current, Color::White, zeros)?;
+                pixels(current, Color::Black, 1)?;
+            }
+            // `000001`: five white pixels
+            5 => pixels(current, Color::White, 5)?,
+            // exit code: up to four white pixels, then the color of the next run
+            _ => {
+                pixels(current, Color::White, zeros - 6)?;
+                let tag = reader.peek(1).ok_or(DecodeError::Invalid)?;
+                reader.consume(1).map_err(DecodeError::Reader)?;
+                let next = if tag == 1 { Color::Black } else { Color::White };
+                if next != color {
+                    flip(current, a0, width);
+                }
+                return Ok((a0, next));
+            }
+        }
+    }
+}
+
 /// Decode a Group 4 Image
 ///
 /// - `width` is the width of the image.
@@ -209,6 +712,100 @@ pub fn decode_g4(
     Some(())
 }
 
+/// Decode a Group 4 Image into packed rows.
+///
+/// The callback `row_cb` is called with each line packed by `pack_row`,
+/// `(width + 7) / 8` bytes with bits set according to `polarity`.
+/// `height` is handled like in `decode_g4`.
+pub fn decode_g4_packed(
+    input: impl Iterator<Item = u8>,
+    width: u32,
+    height: Option<u32>,
+    polarity: Polarity,
+    mut row_cb: impl FnMut(&[u8]),
+) -> Option<()> {
+    let mut row = vec![0; (width as usize + 7) / 8];
+    decode_g4(input, width, height, |transitions| {
+        pack_row(transitions, width, polarity, &mut row);
+        row_cb(&row);
+    })
+}
+
+/// Decode a Group 4 Image into a `Bitmap`.
+///
+/// `height` is handled like in `decode_g4`.
+pub fn decode_g4_bitmap(
+    input: impl Iterator<Item = u8>,
+    width: u32,
+    height: Option<u32>,
+) -> Option<Bitmap> {
+    let mut bitmap = Bitmap::new(width, 0);
+    decode_g4(input, width, height, |transitions| {
+        bitmap.push_transitions(transitions)
+    })?;
+    Some(bitmap)
+}
+
+/// How lines that could not be decoded are filled in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Concealment {
+    /// Repeat the last line that decoded correctly.
+    Repeat,
+    /// Emit all-white lines.
+    White,
+}
+
+/// Decode a Group 4 Image, keeping as much as possible of a damaged one.
+///
+/// Exactly `height` lines are passed to `line_cb`.
+/// Once a line fails to decode, it and all remaining lines are filled according to `conceal`.
+///
+/// Returns the index of the first line that failed to decode, or `None` if the image is intact.
+/// An EOFB before `height` lines is not an error; the rest is padded white like in `decode_g4`.
+pub fn decode_g4_best_effort(
+    input: impl Iterator<Item = u8>,
+    width: u32,
+    height: u32,
+    conceal: Concealment,
+    mut line_cb: impl FnMut(&[u32]),
+) -> Option<u32> {
+    let reader = input.map(Result::<u8, Infallible>::Ok);
+    let mut decoder = Group4Decoder::new(reader, width).ok();
+
+    let mut last_good: Vec<u32> = vec![];
+    let mut lines_emitted: u32 = 0;
+    let mut broken_at = None;
+
+    if let Some(ref mut decoder) = decoder {
+        while lines_emitted < height {
+            match decoder.advance() {
+                Ok(DecodeStatus::Incomplete) => {
+                    line_cb(decoder.transition());
+                    lines_emitted += 1;
+                }
+                Ok(DecodeStatus::End) => break,
+                Err(_) => {
+                    if conceal == Concealment::Repeat {
+                        last_good.extend_from_slice(decoder.transition());
+                    }
+                    broken_at = Some(lines_emitted);
+                    break;
+                }
+            }
+        }
+    } else if height > 0 {
+        broken_at = Some(0);
+    }
+
+    // `transition()` still holds the last good line after a failed `advance`.
+    while lines_emitted < height {
+        line_cb(&last_good);
+        lines_emitted += 1;
+    }
+
+    broken_at
+}
+
 #[derive(Debug)]
 pub enum DecodeError<E> {
     Reader(E),
@@ -227,6 +824,8 @@ pub struct Group4Decoder<R> {
     reference: Vec<u32>,
     current: Vec<u32>,
     width: u32,
+    byte_align: bool,
+    end_of_block: bool,
 }
 impl<E, R: Iterator<Item = Result<u8, E>>> Group4Decoder<R> {
     pub fn new(reader: R, width: u32) -> Result<Self, E> {
@@ -235,96 +834,59 @@ impl<E, R: Iterator<Item = Result<u8, E>>> Group4Decoder<R> {
             reference: Vec::new(),
             current: Vec::new(),
             width,
+            byte_align: false,
+            end_of_block: true,
         })
     }
+    /// Skip fill bits so that every line starts at a byte boundary.
+    ///
+    /// This corresponds to `/EncodedByteAlign true` in PDF.
+    pub fn with_byte_align(mut self, enabled: bool) -> Self {
+        self.byte_align = enabled;
+        self
+    }
+    /// Whether an EOFB ends the data, which is the default.
+    ///
+    /// This corresponds to `/EndOfBlock` in PDF. Without it, an EOFB is invalid data
+    /// and the caller has to stop after the known number of lines.
+    pub fn with_end_of_block(mut self, enabled: bool) -> Self {
+        self.end_of_block = enabled;
+        self
+    }
+    /// Set the order of bits within each byte of the input.
+    ///
+    /// Has to be called before the first line is decoded.
+    pub fn with_fill_order(mut self, fill_order: FillOrder) -> Self {
+        self.reader.set_fill_order(fill_order);
+        self
+    }
     // when Complete::Complete is returned, there is no useful data in .transitions() or .line()
     pub fn advance(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
-        let mut transitions = Transitions::new(&self.reference);
-        let mut a0 = 0;
-        let mut color = Color::White;
-        let mut start_of_row = true;
-        //debug!("\n\nline {}", y);
-
-        loop {
-            //reader.print_peek();
-            let mode = match mode::decode(&mut self.reader) {
-                Some(mode) => mode,
-                None => return Err(DecodeError::Invalid),
-            };
-            //debug!("  {:?}, color={:?}, a0={}", mode, color, a0);
-
-            match mode {
-                Mode::Pass => {
-                    if start_of_row && color == Color::White {
-                        transitions.pos += 1;
-                    } else {
-                        transitions
-                            .next_color(a0, !color, false)
-                            .ok_or(DecodeError::Invalid)?;
-                    }
-                    //debug!("b1={}", b1);
-                    if let Some(b2) = transitions.next() {
-                        //debug!("b2={}", b2);
-                        a0 = b2;
-                    }
-                }
-                Mode::Vertical(delta) => {
-                    let b1 = transitions
-                        .next_color(a0, !color, start_of_row)
-                        .unwrap_or(self.width);
-                    let a1_i32 = b1 as i32 + delta as i32;
-                    if a1_i32 < 0 || a1_i32 > self.width as i32 {
-                        break;
-                    }
-                    let a1 = a1_i32 as u32;
-                    //debug!("transition to {:?} at {}", !color, a1);
-                    // Canonical form: only store transitions strictly less
-                    // than width. A transition at width is the implicit
-                    // end-of-line and is not a color change. This matches
-                    // the encoder's `self.current` representation (see
-                    // encoder.rs — it only pushes values yielded by pels,
-                    // which are always in [0, width-1]).
-                    if a1 < self.width {
-                        self.current.push(a1);
-                    }
-                    color = !color;
-                    a0 = a1;
-                    if delta < 0 {
-                        transitions.seek_back(a0);
-                    }
-                }
-                Mode::Horizontal => {
-                    let a0a1 = colored(color, &mut self.reader).ok_or(DecodeError::Invalid)?;
-                    let a1a2 = colored(!color, &mut self.reader).ok_or(DecodeError::Invalid)?;
-                    let a1 = a0.checked_add(a0a1).ok_or(DecodeError::Invalid)?;
-                    let a2 = a1.checked_add(a1a2).ok_or(DecodeError::Invalid)?;
-                    //debug!("a0a1={}, a1a2={}, a1={}, a2={}", a0a1, a1a2, a1, a2);
-
-                    // Same canonical form rule: never store a transition
-                    // at width (it's the end-of-line sentinel, not a flip).
-                    if a1 < self.width {
-                        self.current.push(a1);
-                    }
-                    if a2 >= self.width {
-                        break;
-                    }
-                    self.current.push(a2);
-                    a0 = a2;
-                }
-                Mode::Extension => {
-                    let _ext = self.reader.peek(3).ok_or(DecodeError::Invalid)?;
-                    let _ = self.reader.consume(3);
-                    return Err(DecodeError::Unsupported);
-                }
-                Mode::EOF => return Ok(DecodeStatus::End),
-            }
-            start_of_row = false;
-
-            if a0 >= self.width {
-                break;
+        let result = self.decode_line();
+        with_read_error(&mut self.reader, result)
+    }
+    fn decode_line(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        if self.byte_align {
+            let fill = self.reader.bits_to_byte_boundary();
+            self.reader.consume(fill).map_err(DecodeError::Reader)?;
+        }
+        // the data may end without an EOFB
+        if at_end(&self.reader) {
+            return Ok(DecodeStatus::End);
+        }
+        let status = decode_2d_line(
+            &mut self.reader,
+            &self.reference,
+            &mut self.current,
+            self.width,
+            false,
+        )?;
+        if status == DecodeStatus::End {
+            if !self.end_of_block {
+                return Err(DecodeError::Invalid);
             }
+            return Ok(DecodeStatus::End);
         }
-        //debug!("{:?}", current);
 
         std::mem::swap(&mut self.reference, &mut self.current);
         self.current.clear();
@@ -336,13 +898,19 @@ impl<E, R: Iterator<Item = Result<u8, E>>> Group4Decoder<R> {
         &self.reference
     }
 
-    pub fn line(&self) -> Line {
+    pub fn line(&self) -> Line<'_> {
         Line {
             transitions: &self.reference,
             width: self.width,
         }
     }
 }
+impl<R: io::Read> Group4Decoder<ReadBytes<R>> {
+    /// Construct a decoder reading from `read` in buffered chunks.
+    pub fn from_read(read: R, width: u32) -> Result<Self, io::Error> {
+        Self::new(ReadBytes::new(read), width)
+    }
+}
 
 pub struct Line<'a> {
     pub transitions: &'a [u32],
@@ -350,13 +918,132 @@ pub struct Line<'a> {
 }
 impl<'a> Line<'a> {
     pub fn pels(&self) -> impl Iterator<Item = Color> + 'a {
-        pels(&self.transitions, self.width)
+        pels(self.transitions, self.width)
+    }
+}
+
+/// Decode a TIFF "CCITT RLE" (compression 2) image.
+///
+/// Lines are coded one-dimensionally (Modified Huffman) without EOLs
+/// and every line starts at a byte boundary.
+///
+/// - `width` and `height` are the dimensions of the image.
+/// - The callback `line_cb` is called for each decoded line.
+///   The argument is the list of positions of color change, starting with white.
+pub fn decode_rle(
+    input: impl Iterator<Item = u8>,
+    width: u32,
+    height: u32,
+    line_cb: impl FnMut(&[u32]),
+) -> Option<()> {
+    let reader = input.map(Result::<u8, Infallible>::Ok);
+    let decoder = RleDecoder::new(reader, width).ok()?;
+    run_rle(decoder, height, line_cb)
+}
+
+/// Decode a TIFF "CCITT RLEW" (compression 32771) image.
+///
+/// Like `decode_rle`, but every line starts at a 16-bit word boundary.
+pub fn decode_rlew(
+    input: impl Iterator<Item = u8>,
+    width: u32,
+    height: u32,
+    line_cb: impl FnMut(&[u32]),
+) -> Option<()> {
+    let reader = input.map(Result::<u8, Infallible>::Ok);
+    let decoder = RleDecoder::new_word_aligned(reader, width).ok()?;
+    run_rle(decoder, height, line_cb)
+}
+
+fn run_rle<E, R: Iterator<Item = Result<u8, E>>>(
+    mut decoder: RleDecoder<R>,
+    height: u32,
+    mut line_cb: impl FnMut(&[u32]),
+) -> Option<()> {
+    for _ in 0..height {
+        if decoder.advance().ok()? == DecodeStatus::End {
+            return None;
+        }
+        line_cb(decoder.transitions());
+    }
+    Some(())
+}
+
+pub struct RleDecoder<R> {
+    reader: ByteReader<R>,
+    current: Vec<u32>,
+    width: u32,
+    word_aligned: bool,
+}
+impl<E, R: Iterator<Item = Result<u8, E>>> RleDecoder<R> {
+    pub fn new(reader: R, width: u32) -> Result<Self, E> {
+        Ok(RleDecoder {
+            reader: ByteReader::new(reader)?,
+            current: Vec::new(),
+            width,
+            word_aligned: false,
+        })
+    }
+    /// Construct a decoder for CCITT RLEW, where lines start at 16-bit word boundaries.
+    pub fn new_word_aligned(reader: R, width: u32) -> Result<Self, E> {
+        Ok(RleDecoder {
+            word_aligned: true,
+            ..Self::new(reader, width)?
+        })
+    }
+    /// Set the order of bits within each byte of the input.
+    ///
+    /// Has to be called before the first line is decoded.
+    pub fn with_fill_order(mut self, fill_order: FillOrder) -> Self {
+        self.reader.set_fill_order(fill_order);
+        self
+    }
+    /// Decode the next line.
+    ///
+    /// `DecodeStatus::End` is returned if there is no data left.
+    pub fn advance(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        let result = self.decode_line();
+        with_read_error(&mut self.reader, result)
+    }
+    fn decode_line(&mut self) -> Result<DecodeStatus, DecodeError<E>> {
+        self.current.clear();
+        if self.reader.peek(1).is_none() {
+            return Ok(DecodeStatus::End);
+        }
+
+        let mut a0: u32 = 0;
+        let mut color = Color::White;
+        while a0 < self.width {
+            let p = colored(color, &mut self.reader).ok_or(DecodeError::Invalid)?;
+            a0 = a0.checked_add(p).ok_or(DecodeError::Invalid)?;
+            if a0 > self.width {
+                return Err(DecodeError::Invalid);
+            }
+            if a0 < self.width {
+                self.current.push(a0);
+            }
+            color = !color;
+        }
+
+        // The next line starts at a byte boundary.
+        let fill = self.reader.bits_to_byte_boundary();
+        self.reader.consume(fill).map_err(DecodeError::Reader)?;
+        // RLEW pads to a whole 16-bit word.
+        if self.word_aligned && self.reader.bit_position() % 16 != 0 {
+            self.reader.consume(8).map_err(DecodeError::Reader)?;
+        }
+
+        Ok(DecodeStatus::Incomplete)
+    }
+    pub fn transitions(&self) -> &[u32] {
+        &self.current
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pack_bits;
 
     /// Fuzz artifact: 5 bytes that triggered checked_add overflow in G4
     /// horizontal mode before the fix. The overflow is now caught by
@@ -478,9 +1165,471 @@ mod tests {
             // Canonical form: decoder must not append the width sentinel.
             assert!(
                 decoded_line.iter().all(|&t| t < width),
-                "decoder produced non-canonical transition list {decoded_line:?} \
-                 (contains width={width}); transitions should all be < width"
+                "decoder produced non-canonical transition list {:?} \
+                 (contains width={}); transitions should all be < width",
+                decoded_line,
+                width
             );
         }
     }
+
+    /// Group 3 1D encoder output must decode back to the same pels,
+    /// including lines that start with black and lines longer than 64.
+    #[test]
+    fn g3_roundtrip() {
+        let width = 200u32;
+        let lines: Vec<Vec<u32>> = vec![vec![], vec![0], vec![3, 70, 71, 199], vec![150]];
+        let mut encoder = crate::encoder::Group3Encoder::new(crate::VecWriter::new());
+        for transitions in &lines {
+            encoder
+                .encode_line(super::pels(transitions, width), width)
+                .unwrap();
+        }
+        let encoded = encoder.finish().unwrap().finish();
+
+        let mut decoded = Vec::new();
+        let result = decode_g3(encoded.into_iter(), |line| {
+            decoded.push(super::pels(line, width).collect::<Vec<_>>());
+        });
+        assert_eq!(result, Some(()));
+        let expected: Vec<Vec<_>> = lines
+            .iter()
+            .map(|t| super::pels(t, width).collect())
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    /// Modified READ output must decode back to the same pels for
+    /// different `k`, including 2D lines referring to a 1D line.
+    #[test]
+    fn g3_2d_roundtrip() {
+        let width = 100u32;
+        let lines: Vec<Vec<u32>> = vec![
+            vec![10, 20],
+            vec![11, 19],
+            vec![],
+            vec![0, 50, 51, 99],
+            vec![2, 48, 52, 90],
+            vec![30],
+        ];
+        let expected: Vec<Vec<_>> = lines
+            .iter()
+            .map(|t| super::pels(t, width).collect())
+            .collect();
+        for k in 1..=4 {
+            let mut encoder = crate::encoder::Group3Encoder::new_2d(crate::VecWriter::new(), k);
+            for transitions in &lines {
+                encoder
+                    .encode_line(super::pels(transitions, width), width)
+                    .unwrap();
+            }
+            let encoded = encoder.finish().unwrap().finish();
+
+            let mut decoded = Vec::new();
+            let result = decode_g3_2d(encoded.into_iter(), width, |line| {
+                decoded.push(super::pels(line, width).collect::<Vec<_>>());
+            });
+            assert_eq!(result, Some(()), "k={}", k);
+            assert_eq!(decoded, expected, "k={}", k);
+        }
+    }
+
+    /// CCITT RLE lines are byte aligned and have no EOLs.
+    #[test]
+    fn rle_roundtrip() {
+        let width = 12u32;
+        let lines: Vec<Vec<u32>> = vec![vec![], vec![0, 4], vec![3, 5, 11]];
+        let mut encoder = crate::encoder::RleEncoder::new(crate::VecWriter::new());
+        for transitions in &lines {
+            encoder
+                .encode_line(super::pels(transitions, width), width)
+                .unwrap();
+        }
+        let encoded = encoder.finish().unwrap().finish();
+        // white(12)=001000 | white(0)=00110101 black(4)=011 white(8)=10011 |
+        // white(3)=1000 black(2)=11 white(6)=1110 black(1)=010
+        assert_eq!(
+            encoded,
+            [
+                0b0010_0000,
+                0b0011_0101,
+                0b0111_0011,
+                0b1000_1111,
+                0b1001_0000
+            ]
+        );
+
+        let mut decoded = Vec::new();
+        let result = decode_rle(encoded.into_iter(), width, 3, |line| {
+            decoded.push(line.to_vec());
+        });
+        assert_eq!(result, Some(()));
+        assert_eq!(decoded, lines);
+    }
+
+    /// CCITT RLEW lines are padded to 16-bit words.
+    #[test]
+    fn rlew_roundtrip() {
+        let width = 12u32;
+        let lines: Vec<Vec<u32>> = vec![vec![], vec![0, 4], vec![3, 5, 11]];
+        let mut encoder = crate::encoder::RleEncoder::new_word_aligned(crate::VecWriter::new());
+        for transitions in &lines {
+            encoder
+                .encode_line(super::pels(transitions, width), width)
+                .unwrap();
+        }
+        let encoded = encoder.finish().unwrap().finish();
+        assert_eq!(
+            encoded,
+            [
+                0b0010_0000,
+                0,
+                0b0011_0101,
+                0b0111_0011,
+                0b1000_1111,
+                0b1001_0000
+            ]
+        );
+
+        let mut decoded = Vec::new();
+        let result = decode_rlew(encoded.into_iter(), width, 3, |line| {
+            decoded.push(line.to_vec());
+        });
+        assert_eq!(result, Some(()));
+        assert_eq!(decoded, lines);
+    }
+
+    /// Lines switching to uncompressed mode (extension 111) and back.
+    #[test]
+    fn g4_uncompressed_mode() {
+        let data = pack_bits(concat!(
+            // line 1: uncompressed B, WB, WWWWW, exit to white
+            "0000001 111 1 01 000001 00000010",
+            // line 2: V0, uncompressed WWWB, W + exit to black, V0
+            "1 0000001 111 0001 000000011 1",
+            // EOFB
+            "000000000001 000000000001",
+        ));
+        let mut lines = Vec::new();
+        let result = decode_g4(data.into_iter(), 8, None, |line| {
+            lines.push(line.to_vec());
+        });
+        assert_eq!(result, Some(()));
+        assert_eq!(lines, vec![vec![0, 1, 2, 3], vec![3, 4, 5]]);
+    }
+
+    /// Noisy lines are shorter in uncompressed mode and must still roundtrip.
+    #[test]
+    fn g4_roundtrip_uncompressed_mode() {
+        let width = 64u32;
+        let checker: Vec<u32> = (1..width).collect();
+        let lines: Vec<Vec<u32>> = vec![
+            checker.clone(),
+            vec![10, 20],
+            (0..width).step_by(2).collect(),
+            checker,
+            vec![],
+        ];
+        let encode = |uncompressed| {
+            let mut encoder = crate::encoder::Encoder::new(crate::VecWriter::new())
+                .with_uncompressed_mode(uncompressed);
+            for transitions in &lines {
+                encoder
+                    .encode_line(super::pels(transitions, width), width)
+                    .unwrap();
+            }
+            encoder.finish().unwrap().finish()
+        };
+        let plain = encode(false);
+        let encoded = encode(true);
+        assert!(encoded.len() < plain.len());
+
+        let mut decoded = Vec::new();
+        let result = decode_g4(encoded.into_iter(), width, None, |line| {
+            decoded.push(super::pels(line, width).collect::<Vec<_>>());
+        });
+        assert_eq!(result, Some(()));
+        let expected: Vec<Vec<_>> = lines
+            .iter()
+            .map(|t| super::pels(t, width).collect())
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    /// A damaged Group 3 line and the 2D lines after it are replaced by the
+    /// previous line, too many consecutive damaged lines are an error.
+    #[test]
+    fn g3_recover_damaged_rows() {
+        let eol = "000000000001";
+        let line = "0111 10 1000"; // white 2, black 3, white 3
+        let damaged = "000000001 11"; // not a valid code
+        let white = "10011"; // white 8
+        let rtc = format!("{eol}1").repeat(6);
+
+        let data = pack_bits(&format!(
+            "{eol}1 {line} {eol}1 {damaged} {eol}1 {white} {rtc}"
+        ));
+        let reader = data.into_iter().map(Result::<u8, Infallible>::Ok);
+        let mut decoder = Group3Decoder::new_2d(reader, 8).unwrap().with_recovery(1);
+        let mut lines = Vec::new();
+        loop {
+            let status = decoder.advance().unwrap();
+            lines.push(decoder.transitions().to_vec());
+            if status == DecodeStatus::End {
+                break;
+            }
+        }
+        assert_eq!(lines, vec![vec![2, 5], vec![2, 5], vec![]]);
+        assert_eq!(decoder.damaged_rows(), 1);
+
+        let data = pack_bits(&format!(
+            "{eol}1 {line} {eol}1 {damaged} {eol}1 {damaged} {eol}1 {white} {rtc}"
+        ));
+        let reader = data.into_iter().map(Result::<u8, Infallible>::Ok);
+        let mut decoder = Group3Decoder::new_2d(reader, 8).unwrap().with_recovery(1);
+        assert!(decoder.advance().is_ok());
+        assert!(decoder.advance().is_ok());
+        assert!(decoder.advance().is_err());
+
+        // 2D lines refer to the damaged line, decoding resumes with a 1D line
+        let pass_v0 = "0001 1"; // all white below [2, 5]
+        let horizontal = "001 0111 10 1"; // white 2, black 3, V0
+        let data = pack_bits(&format!(
+            "{eol}1 {line} {eol}1 {damaged} {eol}0 {pass_v0} {eol}1 {white} {eol}0 {horizontal} {rtc}"
+        ));
+        let reader = data.into_iter().map(Result::<u8, Infallible>::Ok);
+        let mut decoder = Group3Decoder::new_2d(reader, 8).unwrap().with_recovery(1);
+        let mut lines = Vec::new();
+        loop {
+            let status = decoder.advance().unwrap();
+            lines.push(decoder.transitions().to_vec());
+            if status == DecodeStatus::End {
+                break;
+            }
+        }
+        assert_eq!(
+            lines,
+            vec![vec![2, 5], vec![2, 5], vec![2, 5], vec![], vec![2, 5]]
+        );
+        assert_eq!(decoder.damaged_rows(), 2);
+    }
+
+    /// Lines after a damaged one are concealed up to the requested height.
+    #[test]
+    fn g4_best_effort() {
+        let line = "001 0111 10 1"; // white 2, black 3, white 3
+        let same = "111"; // same as the reference
+        let damaged = "0000001 000"; // unsupported extension
+        let data = pack_bits(&format!("{line} {same} {damaged}"));
+
+        for (conceal, fill) in [
+            (Concealment::Repeat, vec![2, 5]),
+            (Concealment::White, vec![]),
+        ] {
+            let mut lines = Vec::new();
+            let broken_at = decode_g4_best_effort(data.iter().cloned(), 8, 4, conceal, |t| {
+                lines.push(t.to_vec())
+            });
+            assert_eq!(broken_at, Some(2));
+            assert_eq!(lines, vec![vec![2, 5], vec![2, 5], fill.clone(), fill]);
+        }
+
+        let eofb = "000000000001".repeat(2);
+        let data = pack_bits(&format!("{line} {eofb}"));
+        let mut lines = Vec::new();
+        let broken_at = decode_g4_best_effort(data.into_iter(), 8, 2, Concealment::Repeat, |t| {
+            lines.push(t.to_vec())
+        });
+        assert_eq!(broken_at, None);
+        assert_eq!(lines, vec![vec![2, 5], vec![]]);
+    }
+
+    /// Decoding straight from `io::Read`, read errors end up in `DecodeError::Reader`.
+    #[test]
+    fn g4_from_read() {
+        let eofb = "000000000001".repeat(2);
+        let data = pack_bits(&format!("001 0111 10 1 111 {eofb}"));
+        let mut decoder = Group4Decoder::from_read(&data[..], 8).unwrap();
+        assert_eq!(decoder.advance().unwrap(), DecodeStatus::Incomplete);
+        assert_eq!(decoder.transition(), &[2, 5]);
+        assert_eq!(decoder.advance().unwrap(), DecodeStatus::Incomplete);
+        assert_eq!(decoder.transition(), &[2, 5]);
+        assert_eq!(decoder.advance().unwrap(), DecodeStatus::End);
+
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "broken"))
+            }
+        }
+        let chained = io::Read::chain(&data[..2], Failing);
+        let mut decoder = Group4Decoder::from_read(chained, 8).unwrap();
+        let error = loop {
+            match decoder.advance() {
+                Ok(DecodeStatus::Incomplete) => continue,
+                Ok(DecodeStatus::End) => panic!("read error not reported"),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(error, DecodeError::Reader(e) if e.to_string() == "broken"));
+    }
+
+    /// LSB-first data is the bit-reversed MSB-first stream and decodes the same.
+    #[test]
+    fn fill_order_roundtrip() {
+        let width = 100u32;
+        let lines: Vec<Vec<u32>> = vec![vec![10, 20], vec![11, 19], vec![], vec![0, 50, 51, 99]];
+
+        let encode_g3 = |fill_order| {
+            let writer = crate::VecWriter::new().with_fill_order(fill_order);
+            let mut encoder = crate::encoder::Group3Encoder::new_2d(writer, 2);
+            for transitions in &lines {
+                encoder
+                    .encode_line(super::pels(transitions, width), width)
+                    .unwrap();
+            }
+            encoder.finish().unwrap().finish()
+        };
+        let msb = encode_g3(FillOrder::MsbFirst);
+        let lsb = encode_g3(FillOrder::LsbFirst);
+        assert_eq!(
+            lsb,
+            msb.iter().map(|b| b.reverse_bits()).collect::<Vec<u8>>()
+        );
+
+        let reader = lsb.into_iter().map(Result::<u8, Infallible>::Ok);
+        let mut decoder = Group3Decoder::new_2d(reader, width)
+            .unwrap()
+            .with_fill_order(FillOrder::LsbFirst);
+        let mut decoded = Vec::new();
+        loop {
+            let status = decoder.advance().unwrap();
+            decoded.push(decoder.transitions().to_vec());
+            if status == DecodeStatus::End {
+                break;
+            }
+        }
+        assert_eq!(decoded, lines);
+
+        let mut encoder = crate::encoder::Encoder::new(
+            crate::VecWriter::new().with_fill_order(FillOrder::LsbFirst),
+        );
+        for transitions in &lines {
+            encoder
+                .encode_line(super::pels(transitions, width), width)
+                .unwrap();
+        }
+        let lsb = encoder.finish().unwrap().finish();
+        let reader = lsb.into_iter().map(Result::<u8, Infallible>::Ok);
+        let mut decoder = Group4Decoder::new(reader, width)
+            .unwrap()
+            .with_fill_order(FillOrder::LsbFirst);
+        let mut decoded = Vec::new();
+        while decoder.advance().unwrap() == DecodeStatus::Incomplete {
+            decoded.push(decoder.transition().to_vec());
+        }
+        assert_eq!(decoded, lines);
+    }
+
+    /// `Group3Decoder::new` fails right away without an initial EOL.
+    #[test]
+    fn g3_initial_eol() {
+        for data in [vec![], vec![0; 4]] {
+            let reader = data.into_iter().map(Result::<u8, Infallible>::Ok);
+            assert!(Group3Decoder::new(reader).is_err());
+        }
+
+        // the fill order can still be changed afterwards
+        let writer = crate::VecWriter::new().with_fill_order(FillOrder::LsbFirst);
+        let mut encoder = crate::encoder::Group3Encoder::new(writer);
+        encoder.encode_line(super::pels(&[3], 8), 8).unwrap();
+        let data = encoder.finish().unwrap().finish();
+        let reader = data.into_iter().map(Result::<u8, Infallible>::Ok);
+        let mut decoder = Group3Decoder::new(reader)
+            .unwrap()
+            .with_fill_order(FillOrder::LsbFirst);
+        decoder.advance().unwrap();
+        assert_eq!(decoder.transitions(), &[3, 8]);
+    }
+
+    /// `pack_row` agrees with packing `pels` one pixel at a time.
+    #[test]
+    fn pack_row_matches_pels() {
+        let lines: &[&[u32]] = &[
+            &[],
+            &[0],
+            &[7, 8],
+            &[1, 2, 3, 4, 5, 6, 7],
+            &[3, 45, 46, 64, 65],
+            &[16, 16, 24],
+            &[9, 70],
+            &[0, 1000],
+        ];
+        for width in [1, 7, 8, 9, 64, 70, 71] {
+            for &line in lines {
+                for polarity in [Polarity::WhiteIsZero, Polarity::BlackIsZero] {
+                    let mut expected = vec![0u8; (width as usize + 7) / 8];
+                    for (i, c) in pels(line, width).enumerate() {
+                        if polarity.bit(c) {
+                            expected[i / 8] |= 0x80 >> (i % 8);
+                        }
+                    }
+                    let mut out = vec![0x55; expected.len() + 1];
+                    pack_row(line, width, polarity, &mut out);
+                    assert_eq!(out[..expected.len()], expected, "{line:?} width {width}");
+                    assert_eq!(out[expected.len()], 0x55);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn g4_packed() {
+        let eofb = "000000000001".repeat(2);
+        let data = pack_bits(&format!("001 0111 10 1 {eofb}"));
+        let mut rows = Vec::new();
+        decode_g4_packed(data.into_iter(), 8, Some(2), Polarity::BlackIsZero, |row| {
+            rows.push(row.to_vec())
+        });
+        assert_eq!(rows, vec![vec![0b1100_0111], vec![0xff]]);
+    }
+
+    /// `encode_row` on packed rows produces the same stream as `encode_line`.
+    #[test]
+    fn encode_row_matches_encode_line() {
+        use crate::encoder::Encoder;
+        use crate::{slice_pels, VecWriter};
+
+        // simple LCG, long runs mixed with noise
+        let mut state = 12345u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        };
+        for width in [1, 7, 63, 64, 65, 130, 1728] {
+            let stride = (width as usize + 7) / 8;
+            let rows: Vec<Vec<u8>> = (0..20)
+                .map(|i| {
+                    (0..stride)
+                        .map(|_| match i % 3 {
+                            0 => next(),
+                            1 => [0, 0xff, 0x0f][next() as usize % 3],
+                            _ => 0,
+                        })
+                        .collect()
+                })
+                .collect();
+
+            let mut by_row = Encoder::new(VecWriter::new());
+            let mut by_line = Encoder::new(VecWriter::new());
+            for row in &rows {
+                by_row.encode_row(row, width).unwrap();
+                let pels = slice_pels(row, width, Polarity::WhiteIsZero);
+                by_line.encode_line(pels, width).unwrap();
+            }
+            let by_row = by_row.finish().unwrap().finish();
+            let by_line = by_line.finish().unwrap().finish();
+            assert_eq!(by_row, by_line, "width {width}");
+        }
+    }
 }
@@ -1,20 +1,19 @@
+use std::convert::Infallible;
+
 use crate::{
-    maps::{black, mode, white, Mode, EDFB_HALF},
-    BitWriter, Color, Transitions,
+    bitmap::Bitmap,
+    maps::{black, mode, white, Mode, EDFB_HALF, EOL},
+    BitWriter, Bits, Color, Transitions,
 };
 
-fn absdiff(a: u32, b: u32) -> u32 {
-    if a > b {
-        a - b
-    } else {
-        b - a
-    }
-}
-
 pub struct Encoder<W> {
-    writer: W,
+    writer: Aligned<W>,
     reference: Vec<u32>,
     current: Vec<u32>,
+    uncompressed: bool,
+    byte_align: bool,
+    /// scratch space for `encode_row`
+    changes: Vec<u32>,
 }
 fn encode_color<W: BitWriter>(writer: &mut W, color: Color, mut n: u32) -> Result<(), W::Error> {
     let table = match color {
@@ -41,101 +40,442 @@ fn encode_color<W: BitWriter>(writer: &mut W, color: Color, mut n: u32) -> Resul
 
     write(n)
 }
+
+/// Positions where the color of `pels` changes, starting with white.
+fn color_changes(pels: impl Iterator<Item = Color>) -> impl Iterator<Item = u32> {
+    pels.enumerate()
+        .scan(Color::White, |state, (i, c)| {
+            Some(if c != *state {
+                debug!("  {i} {c:?}");
+                *state = c;
+                Some(i as u32)
+            } else {
+                None
+            })
+        })
+        .flatten()
+}
+
+/// Find the color changes of a packed row (`1` is black), 64 pixels at a time.
+fn row_changes(row: &[u8], width: u32, out: &mut Vec<u32>) {
+    out.clear();
+    let row = &row[..(width as usize + 7) / 8];
+    // the pixel before each word, the line starts with an imaginary white one
+    let mut prev = 0u64;
+    for (i, chunk) in row.chunks(8).enumerate() {
+        let mut bytes = [0; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let word = u64::from_be_bytes(bytes);
+
+        let mut changes = word ^ (word >> 1 | prev << 63);
+        while changes != 0 {
+            let bit = changes.leading_zeros();
+            let pos = i as u32 * 64 + bit;
+            if pos >= width {
+                return;
+            }
+            out.push(pos);
+            changes &= !(1 << (63 - bit));
+        }
+        prev = word & 1;
+    }
+}
+
+/// Write one line as alternating white and black run lengths (Modified Huffman).
+fn encode_runs<W: BitWriter>(
+    writer: &mut W,
+    changes: impl Iterator<Item = u32>,
+    width: u32,
+) -> Result<(), W::Error> {
+    let mut color = Color::White;
+    let mut a0 = 0;
+    for a1 in changes.take_while(|&a1| a1 < width).chain(Some(width)) {
+        encode_color(writer, color, a1 - a0)?;
+        a0 = a1;
+        color = !color;
+    }
+    Ok(())
+}
+
+/// Wraps a `BitWriter` and keeps track of the bit position, so lines can be aligned.
+///
+/// The wrapped writer is assumed to start at a byte boundary.
+struct Aligned<W> {
+    writer: W,
+    pos: u32,
+}
+impl<W: BitWriter> Aligned<W> {
+    fn new(writer: W) -> Self {
+        Aligned { writer, pos: 0 }
+    }
+    /// Write `0` bits until the position is a multiple of `align` bits (at most 16).
+    fn pad(&mut self, align: u32) -> Result<(), W::Error> {
+        let n = (align - self.pos % align) % align;
+        if n > 0 {
+            self.write(Bits {
+                data: 0,
+                len: n as u8,
+            })?;
+        }
+        Ok(())
+    }
+}
+impl<W: BitWriter> BitWriter for Aligned<W> {
+    type Error = W::Error;
+    fn write(&mut self, bits: Bits) -> Result<(), Self::Error> {
+        self.pos = self.pos.wrapping_add(bits.len as u32);
+        self.writer.write(bits)
+    }
+}
+
+/// Encode one line two-dimensionally against `reference`.
+///
+/// The color changes of the line are collected in `current`.
+fn encode_2d_line<W: BitWriter>(
+    writer: &mut W,
+    reference: &[u32],
+    current: &mut Vec<u32>,
+    mut pels: impl Iterator<Item = u32>,
+    width: u32,
+) -> Result<(), W::Error> {
+    let mut color = Color::White;
+    let mut transitions = Transitions::new(reference);
+    let mut a0 = 0;
+    let mut start_of_line = true;
+    current.clear();
+
+    while a0 < width {
+        let a1;
+        if let Some(a1_) = pels.next() {
+            current.push(a1_);
+            a1 = a1_;
+        } else {
+            a1 = width;
+        }
+        loop {
+            transitions.seek_back(a0);
+            let b1 = transitions
+                .next_color(a0, !color, start_of_line)
+                .unwrap_or(width);
+            let b2 = transitions.peek();
+            start_of_line = false;
+            debug!("  a0={a0}, a1={a1}, b1={:?}, b2={:?}", b1, b2);
+            match (b1, b2) {
+                (_b1, Some(b2)) if b2 < a1 => {
+                    debug!("  Pass");
+                    let bits = mode::encode(Mode::Pass).unwrap();
+                    writer.write(bits)?;
+                    transitions.skip(1);
+                    a0 = b2;
+                    continue;
+                }
+                (b1, _) if a1.abs_diff(b1) <= 3 => {
+                    let delta = a1 as i16 - b1 as i16;
+                    debug!("  Vertical({})", delta);
+                    let bits = mode::encode(Mode::Vertical(delta as i8)).unwrap();
+                    writer.write(bits)?;
+                    a0 = a1;
+                    color = !color;
+                }
+                _ => {
+                    let a2 = match pels.next() {
+                        Some(a2) => {
+                            current.push(a2);
+                            a2
+                        }
+                        None => width,
+                    };
+                    let a0a1 = a1.saturating_sub(a0);
+                    let a1a2 = a2.saturating_sub(a1);
+                    debug!("  Horizontal({}, {}) color={color:?}", a0a1, a1a2);
+                    let bits = mode::encode(Mode::Horizontal).unwrap();
+                    writer.write(bits)?;
+                    let c = if a0 + a1 == 0 { Color::White } else { color };
+                    encode_color(writer, c, a0a1)?;
+                    encode_color(writer, !c, a1a2)?;
+                    a0 = a2;
+                }
+            }
+            break;
+        }
+    }
+
+    Ok(())
+}
+/// Counts the bits written instead of storing them.
+struct BitCount(u32);
+impl BitWriter for BitCount {
+    type Error = Infallible;
+    fn write(&mut self, bits: Bits) -> Result<(), Self::Error> {
+        self.0 += bits.len as u32;
+        Ok(())
+    }
+}
+
+/// Encode one line in uncompressed mode (extension `111`).
+///
+/// `changes` must be in canonical form, i.e. all positions are less than `width`.
+fn encode_uncompressed<W: BitWriter>(
+    writer: &mut W,
+    changes: &[u32],
+    width: u32,
+) -> Result<(), W::Error> {
+    writer.write(mode::encode(Mode::Extension).unwrap())?;
+    writer.write(Bits {
+        data: 0b111,
+        len: 3,
+    })?;
+
+    let mut whites = 0;
+    let mut a0 = 0;
+    let mut color = Color::White;
+    for a1 in changes.iter().copied().chain(Some(width)) {
+        let n = a1 - a0;
+        match color {
+            Color::White => whites += n,
+            Color::Black => {
+                for _ in 0..n {
+                    // `000001` for five white pixels
+                    while whites >= 5 {
+                        writer.write(Bits { data: 1, len: 6 })?;
+                        whites -= 5;
+                    }
+                    // `0..01` for up to four white pixels and one black pixel
+                    writer.write(Bits {
+                        data: 1,
+                        len: whites as u8 + 1,
+                    })?;
+                    whites = 0;
+                }
+            }
+        }
+        a0 = a1;
+        color = !color;
+    }
+    while whites >= 5 {
+        writer.write(Bits { data: 1, len: 6 })?;
+        whites -= 5;
+    }
+    // exit code with the remaining white pixels, the next run is white.
+    writer.write(Bits {
+        data: 0b10,
+        len: whites as u8 + 8,
+    })
+}
+
 impl<W: BitWriter> Encoder<W> {
     pub fn new(writer: W) -> Self {
         Encoder {
-            writer,
+            writer: Aligned::new(writer),
             reference: vec![],
             current: vec![],
+            uncompressed: false,
+            byte_align: false,
+            changes: vec![],
         }
     }
+    /// Allow uncompressed mode for lines where it is shorter than the two-dimensional coding.
+    ///
+    /// This mostly helps with dithered or halftone content, where nearly every pixel is
+    /// a color change. Decoders that do not support uncompressed mode can't read such lines.
+    pub fn with_uncompressed_mode(mut self, enabled: bool) -> Self {
+        self.uncompressed = enabled;
+        self
+    }
+    /// Pad every line with `0` bits, so that each line starts at a byte boundary.
+    ///
+    /// This corresponds to `/EncodedByteAlign true` in PDF.
+    pub fn with_byte_align(mut self, enabled: bool) -> Self {
+        self.byte_align = enabled;
+        self
+    }
     pub fn encode_line(
         &mut self,
         pels: impl Iterator<Item = Color>,
         width: u32,
     ) -> Result<(), W::Error> {
-        let mut color = Color::White;
-        let mut transitions = Transitions::new(&self.reference);
-        let mut a0 = 0;
-        let mut start_of_line = true;
-        let mut pels = pels
-            .enumerate()
-            .scan(Color::White, |state, (i, c)| {
-                Some(if c != *state {
-                    debug!("  {i} {c:?}");
-                    *state = c;
-                    Some(i as u32)
-                } else {
-                    None
-                })
-            })
-            .filter_map(|x| x);
-        let writer = &mut self.writer;
-        self.current.clear();
-
-        while a0 < width {
-            let a1;
-            if let Some(a1_) = pels.next() {
-                self.current.push(a1_);
-                a1 = a1_;
+        self.encode_changes(color_changes(pels), width)
+    }
+    /// Encode a packed row, MSB-first with `1` for black.
+    ///
+    /// `row` has to hold at least `(width + 7) / 8` bytes, bits after `width` are ignored.
+    /// Color changes are found a word at a time, which is much faster than `encode_line`.
+    pub fn encode_row(&mut self, row: &[u8], width: u32) -> Result<(), W::Error> {
+        let mut changes = std::mem::take(&mut self.changes);
+        row_changes(row, width, &mut changes);
+        let result = self.encode_changes(changes.iter().copied(), width);
+        self.changes = changes;
+        result
+    }
+    fn encode_changes(
+        &mut self,
+        changes: impl Iterator<Item = u32>,
+        width: u32,
+    ) -> Result<(), W::Error> {
+        if self.uncompressed {
+            let changes: Vec<u32> = changes.take_while(|&a1| a1 < width).collect();
+
+            let mut coded = BitCount(0);
+            let _ = encode_2d_line(
+                &mut coded,
+                &self.reference,
+                &mut self.current,
+                changes.iter().copied(),
+                width,
+            );
+            let mut literal = BitCount(0);
+            let _ = encode_uncompressed(&mut literal, &changes, width);
+
+            if literal.0 < coded.0 {
+                debug!("  Uncompressed");
+                encode_uncompressed(&mut self.writer, &changes, width)?;
+                self.current = changes;
             } else {
-                a1 = width;
-            }
-            loop {
-                transitions.seek_back(a0);
-                let b1 = transitions
-                    .next_color(a0, !color, start_of_line)
-                    .unwrap_or(width);
-                let b2 = transitions.peek();
-                start_of_line = false;
-                debug!("  a0={a0}, a1={a1}, b1={:?}, b2={:?}", b1, b2);
-                match (b1, b2) {
-                    (_b1, Some(b2)) if b2 < a1 => {
-                        debug!("  Pass");
-                        let bits = mode::encode(Mode::Pass).unwrap();
-                        writer.write(bits)?;
-                        transitions.skip(1);
-                        a0 = b2;
-                        continue;
-                    }
-                    (b1, _) if absdiff(a1, b1) <= 3 => {
-                        let delta = a1 as i16 - b1 as i16;
-                        debug!("  Vertical({})", delta);
-                        let bits = mode::encode(Mode::Vertical(delta as i8)).unwrap();
-                        writer.write(bits)?;
-                        a0 = a1;
-                        color = !color;
-                    }
-                    _ => {
-                        let a2 = match pels.next() {
-                            Some(a2) => {
-                                self.current.push(a2);
-                                a2
-                            }
-                            None => width,
-                        };
-                        let a0a1 = a1.saturating_sub(a0);
-                        let a1a2 = a2.saturating_sub(a1);
-                        debug!("  Horizontal({}, {}) color={color:?}", a0a1, a1a2);
-                        let bits = mode::encode(Mode::Horizontal).unwrap();
-                        writer.write(bits)?;
-                        let c = if a0 + a1 == 0 { Color::White } else { color };
-                        encode_color(writer, c, a0a1)?;
-                        encode_color(writer, !c, a1a2)?;
-                        a0 = a2;
-                    }
-                }
-                break;
+                encode_2d_line(
+                    &mut self.writer,
+                    &self.reference,
+                    &mut self.current,
+                    changes.iter().copied(),
+                    width,
+                )?;
             }
+        } else {
+            encode_2d_line(
+                &mut self.writer,
+                &self.reference,
+                &mut self.current,
+                changes,
+                width,
+            )?;
+        }
+        if self.byte_align {
+            self.writer.pad(8)?;
         }
-
         std::mem::swap(&mut self.reference, &mut self.current);
         debug!("next line");
         Ok(())
     }
+    /// Encode all rows of `bitmap`.
+    pub fn encode_bitmap(&mut self, bitmap: &Bitmap) -> Result<(), W::Error> {
+        for row in bitmap.rows() {
+            self.encode_row(row, bitmap.width())?;
+        }
+        Ok(())
+    }
     pub fn finish(mut self) -> Result<W, W::Error> {
         self.writer.write(EDFB_HALF)?;
         self.writer.write(EDFB_HALF)?;
+        Ok(self.writer.writer)
+    }
+}
+
+/// Group 3 encoder.
+///
+/// Every line is preceded by an EOL, `finish` writes the RTC (six EOLs).
+///
+/// Constructed with `new`, all lines are coded one-dimensionally (Modified Huffman).
+/// With `new_2d` every EOL is followed by a tag bit and only every `k`-th line
+/// is coded one-dimensionally, the lines in between are coded two-dimensionally
+/// (Modified READ).
+pub struct Group3Encoder<W> {
+    writer: W,
+    reference: Vec<u32>,
+    current: Vec<u32>,
+    k: Option<u32>,
+    row: u32,
+}
+impl<W: BitWriter> Group3Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Group3Encoder {
+            writer,
+            reference: vec![],
+            current: vec![],
+            k: None,
+            row: 0,
+        }
+    }
+    /// Construct a Modified READ encoder with the given `k` parameter.
+    ///
+    /// T.4 uses `k = 2` at standard and `k = 4` at fine resolution.
+    pub fn new_2d(writer: W, k: u32) -> Self {
+        Group3Encoder {
+            k: Some(k.max(1)),
+            ..Self::new(writer)
+        }
+    }
+    pub fn encode_line(
+        &mut self,
+        pels: impl Iterator<Item = Color>,
+        width: u32,
+    ) -> Result<(), W::Error> {
+        self.writer.write(EOL)?;
+        let k = match self.k {
+            Some(k) => k,
+            None => return encode_runs(&mut self.writer, color_changes(pels), width),
+        };
+        if self.row % k == 0 {
+            self.writer.write(Bits { data: 1, len: 1 })?;
+            self.current.clear();
+            self.current
+                .extend(color_changes(pels).take_while(|&a1| a1 < width));
+            encode_runs(&mut self.writer, self.current.iter().copied(), width)?;
+        } else {
+            self.writer.write(Bits { data: 0, len: 1 })?;
+            encode_2d_line(
+                &mut self.writer,
+                &self.reference,
+                &mut self.current,
+                color_changes(pels),
+                width,
+            )?;
+        }
+        std::mem::swap(&mut self.reference, &mut self.current);
+        self.row = self.row.wrapping_add(1);
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<W, W::Error> {
+        for _ in 0..6 {
+            self.writer.write(EOL)?;
+            if self.k.is_some() {
+                self.writer.write(Bits { data: 1, len: 1 })?;
+            }
+        }
         Ok(self.writer)
     }
 }
+
+/// Encoder for TIFF "CCITT RLE" (compression 2).
+///
+/// Lines are coded one-dimensionally (Modified Huffman) without EOLs,
+/// and padded so that every line starts at a byte boundary.
+pub struct RleEncoder<W> {
+    writer: Aligned<W>,
+    align: u32,
+}
+impl<W: BitWriter> RleEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        RleEncoder {
+            writer: Aligned::new(writer),
+            align: 8,
+        }
+    }
+    /// Construct an encoder for CCITT RLEW (TIFF compression 32771),
+    /// where lines are padded to 16-bit word boundaries.
+    pub fn new_word_aligned(writer: W) -> Self {
+        RleEncoder {
+            writer: Aligned::new(writer),
+            align: 16,
+        }
+    }
+    pub fn encode_line(
+        &mut self,
+        pels: impl Iterator<Item = Color>,
+        width: u32,
+    ) -> Result<(), W::Error> {
+        encode_runs(&mut self.writer, color_changes(pels), width)?;
+        self.writer.pad(self.align)
+    }
+    pub fn finish(self) -> Result<W, W::Error> {
+        Ok(self.writer.writer)
+    }
+}
@@ -1,8 +1,7 @@
 #![deny(unsafe_code)]
 use std::convert::Infallible;
 use std::fmt;
-use std::io::{self, Read};
-use std::iter::Map;
+use std::io;
 use std::ops::Not;
 
 #[cfg(feature = "debug")]
@@ -18,6 +17,7 @@ macro_rules! debug {
     };
 }
 
+mod lookup;
 pub mod maps;
 
 /// Decoder module
@@ -29,6 +29,12 @@ pub mod encoder;
 /// TIFF helper functions
 pub mod tiff;
 
+/// PDF `CCITTFaxDecode` support
+pub mod pdf;
+
+/// Packed bilevel images
+pub mod bitmap;
+
 /// Trait used to read data bitwise.
 ///
 /// For lazy people `ByteReader` is provided which implements this trait.
@@ -61,6 +67,25 @@ pub trait BitReader {
     fn bits_to_byte_boundary(&self) -> u8;
 }
 
+/// Order of the bits within a byte, as in the TIFF `FillOrder` tag.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FillOrder {
+    /// Most significant bit first (`FillOrder` 1)
+    #[default]
+    MsbFirst,
+    /// Least significant bit first (`FillOrder` 2), used by many fax modems
+    LsbFirst,
+}
+impl FillOrder {
+    /// Convert a byte between this fill order and MSB-first.
+    pub fn apply(self, byte: u8) -> u8 {
+        match self {
+            FillOrder::MsbFirst => byte,
+            FillOrder::LsbFirst => byte.reverse_bits(),
+        }
+    }
+}
+
 /// Trait to write data bitwise
 ///
 /// The `VecWriter` struct is provided for convinience.
@@ -72,6 +97,7 @@ pub struct VecWriter {
     data: Vec<u8>,
     partial: u32,
     len: u8,
+    fill_order: FillOrder,
 }
 impl BitWriter for VecWriter {
     type Error = Infallible;
@@ -79,19 +105,26 @@ impl BitWriter for VecWriter {
         self.partial |= (bits.data as u32) << (32 - self.len - bits.len);
         self.len += bits.len;
         while self.len >= 8 {
-            self.data.push((self.partial >> 24) as u8);
+            self.data
+                .push(self.fill_order.apply((self.partial >> 24) as u8));
             self.partial <<= 8;
             self.len -= 8;
         }
         Ok(())
     }
 }
+impl Default for VecWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl VecWriter {
     pub fn new() -> Self {
         VecWriter {
             data: Vec::new(),
             partial: 0,
             len: 0,
+            fill_order: FillOrder::MsbFirst,
         }
     }
     // with capacity of `n` bits.
@@ -100,13 +133,20 @@ impl VecWriter {
             data: Vec::with_capacity((n + 7) / 8),
             partial: 0,
             len: 0,
+            fill_order: FillOrder::MsbFirst,
         }
     }
+    /// Set the order of bits within each output byte.
+    pub fn with_fill_order(mut self, fill_order: FillOrder) -> Self {
+        self.fill_order = fill_order;
+        self
+    }
 
     /// Pad the output with `0` bits until it is at a byte boundary.
     pub fn pad(&mut self) {
         if self.len > 0 {
-            self.data.push((self.partial >> 24) as u8);
+            self.data
+                .push(self.fill_order.apply((self.partial >> 24) as u8));
             self.partial = 0;
             self.len = 0;
         }
@@ -119,28 +159,123 @@ impl VecWriter {
     }
 }
 
+/// `BitWriter` that streams whole bytes into a `std::io::Write`.
+///
+/// Output is buffered internally, call `finish` to write the last partial byte and flush.
+pub struct IoWriter<W: io::Write> {
+    write: W,
+    buf: Vec<u8>,
+    partial: u32,
+    len: u8,
+    fill_order: FillOrder,
+}
+impl<W: io::Write> BitWriter for IoWriter<W> {
+    type Error = io::Error;
+    fn write(&mut self, bits: Bits) -> Result<(), Self::Error> {
+        self.partial |= (bits.data as u32) << (32 - self.len - bits.len);
+        self.len += bits.len;
+        while self.len >= 8 {
+            self.buf
+                .push(self.fill_order.apply((self.partial >> 24) as u8));
+            self.partial <<= 8;
+            self.len -= 8;
+        }
+        // a single write produces at most two bytes, keep room for them
+        if self.buf.len() + 2 > self.buf.capacity() {
+            self.flush_buf()?;
+        }
+        Ok(())
+    }
+}
+impl<W: io::Write> IoWriter<W> {
+    pub fn new(write: W) -> Self {
+        Self::with_capacity(8192, write)
+    }
+    /// Buffer up to `capacity` bytes before writing them to `write`.
+    pub fn with_capacity(capacity: usize, write: W) -> Self {
+        IoWriter {
+            write,
+            buf: Vec::with_capacity(capacity.max(2)),
+            partial: 0,
+            len: 0,
+            fill_order: FillOrder::MsbFirst,
+        }
+    }
+    /// Set the order of bits within each output byte.
+    pub fn with_fill_order(mut self, fill_order: FillOrder) -> Self {
+        self.fill_order = fill_order;
+        self
+    }
+    fn flush_buf(&mut self) -> io::Result<()> {
+        self.write.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Pad the output with `0` bits until it is at a byte boundary.
+    pub fn pad(&mut self) -> io::Result<()> {
+        if self.len > 0 {
+            self.buf
+                .push(self.fill_order.apply((self.partial >> 24) as u8));
+            self.partial = 0;
+            self.len = 0;
+        }
+        if self.buf.len() + 2 > self.buf.capacity() {
+            self.flush_buf()?;
+        }
+        Ok(())
+    }
+
+    /// pad, write out all buffered bytes and return the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.pad()?;
+        self.flush_buf()?;
+        self.write.flush()?;
+        Ok(self.write)
+    }
+}
+
 pub struct ByteReader<R> {
     read: R,
-    partial: u32,
+    /// up to 64 bits of input, the lowest `valid` of them not consumed yet
+    partial: u64,
     valid: u8,
+    /// number of bytes taken from `read` so far
+    bytes: usize,
+    fill_order: FillOrder,
 }
 impl<E, R: Iterator<Item = Result<u8, E>>> ByteReader<R> {
     /// Construct a new `ByteReader` from an iterator of `u8`
     pub fn new(read: R) -> Result<Self, E> {
+        Self::with_fill_order(read, FillOrder::MsbFirst)
+    }
+    /// Construct a `ByteReader` for input with the given order of bits within each byte.
+    ///
+    /// A read error is only returned if no data could be read,
+    /// otherwise the read is retried once the data runs low.
+    pub fn with_fill_order(read: R, fill_order: FillOrder) -> Result<Self, E> {
         let mut bits = ByteReader {
             read,
             partial: 0,
             valid: 0,
+            bytes: 0,
+            fill_order,
         };
-        bits.fill()?;
-        Ok(bits)
+        match bits.fill() {
+            Err(e) if bits.valid == 0 => Err(e),
+            _ => Ok(bits),
+        }
     }
+    /// Read as many whole bytes ahead as fit into the buffer.
+    ///
+    /// The bits read before an error stay available.
     fn fill(&mut self) -> Result<(), E> {
-        while self.valid < 16 {
+        while self.valid < 56 {
             match self.read.next() {
                 Some(Ok(byte)) => {
-                    self.partial = self.partial << 8 | byte as u32;
+                    self.partial = self.partial << 8 | self.fill_order.apply(byte) as u64;
                     self.valid += 8;
+                    self.bytes += 1;
                 }
                 Some(Err(e)) => return Err(e),
                 None => break,
@@ -148,13 +283,36 @@ impl<E, R: Iterator<Item = Result<u8, E>>> ByteReader<R> {
         }
         Ok(())
     }
+    /// Change the fill order of the input not consumed yet.
+    ///
+    /// Only whole bytes can be reinterpreted, so nothing may have been consumed.
+    fn set_fill_order(&mut self, fill_order: FillOrder) {
+        if fill_order != self.fill_order {
+            debug_assert_eq!(self.bytes * 8, self.valid as usize);
+            let mut partial = 0;
+            for i in 0..self.valid / 8 {
+                let byte = (self.partial >> (8 * i)) as u8;
+                partial |= (byte.reverse_bits() as u64) << (8 * i);
+            }
+            self.partial = partial;
+            self.fill_order = fill_order;
+        }
+    }
+    /// Whether any of the bits read ahead is `1`, which does not depend on the fill order.
+    fn any_set(&self) -> bool {
+        self.partial & ((1 << self.valid) - 1) != 0
+    }
+    /// Number of bits consumed since the start of the input.
+    fn bit_position(&self) -> usize {
+        self.bytes * 8 - self.valid as usize
+    }
     /// Print the remaining data
     ///
     /// Note: For debug purposes only, not part of the API.
     pub fn print_remaining(&mut self) {
         println!(
             "partial: {:0w$b}, valid: {}",
-            self.partial & ((1 << self.valid) - 1),
+            self.partial & ((1u64 << self.valid) - 1),
             self.valid,
             w = self.valid as usize
         );
@@ -166,13 +324,63 @@ impl<E, R: Iterator<Item = Result<u8, E>>> ByteReader<R> {
     pub fn print_peek(&self) {
         println!(
             "partial: {:0w$b}, valid: {}",
-            self.partial & ((1 << self.valid) - 1),
+            self.partial & ((1u64 << self.valid) - 1),
             self.valid,
             w = self.valid as usize
         );
     }
 }
 
+/// Bytes of a `std::io::Read`, pulled in buffered chunks.
+///
+/// Use this to feed a decoder straight from a file or socket.
+/// Read errors are yielded as items and end up in `DecodeError::Reader`.
+pub struct ReadBytes<R> {
+    read: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+}
+impl<R: io::Read> ReadBytes<R> {
+    pub fn new(read: R) -> Self {
+        Self::with_capacity(8192, read)
+    }
+    /// Read chunks of up to `capacity` bytes at a time.
+    pub fn with_capacity(capacity: usize, read: R) -> Self {
+        ReadBytes {
+            read,
+            buf: vec![0; capacity.max(1)].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+        }
+    }
+    /// Return the inner reader.
+    ///
+    /// Bytes that were buffered but not yet yielded are lost.
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+}
+impl<R: io::Read> Iterator for ReadBytes<R> {
+    type Item = io::Result<u8>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos == self.len {
+            match self.read.read(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(n) => {
+                    self.pos = 0;
+                    self.len = n;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let byte = self.buf[self.pos];
+        self.pos += 1;
+        Some(Ok(byte))
+    }
+}
+
 pub fn slice_reader(slice: &[u8]) -> ByteReader<impl Iterator<Item = Result<u8, Infallible>> + '_> {
     ByteReader::new(slice.iter().cloned().map(Ok)).unwrap()
 }
@@ -181,6 +389,16 @@ pub fn slice_bits(slice: &[u8]) -> impl Iterator<Item = bool> + '_ {
         .iter()
         .flat_map(|&b| [7, 6, 5, 4, 3, 2, 1, 0].map(|i| (b >> i) & 1 != 0))
 }
+/// The first `width` pixels of a packed row, with bits interpreted according to `polarity`.
+pub fn slice_pels(
+    slice: &[u8],
+    width: u32,
+    polarity: Polarity,
+) -> impl Iterator<Item = Color> + '_ {
+    slice_bits(slice)
+        .take(width as usize)
+        .map(move |b| polarity.color(b))
+}
 
 impl<E, R: Iterator<Item = Result<u8, E>>> BitReader for ByteReader<R> {
     type Error = E;
@@ -204,13 +422,26 @@ impl<E, R: Iterator<Item = Result<u8, E>>> BitReader for ByteReader<R> {
     }
     fn consume(&mut self, bits: u8) -> Result<(), E> {
         self.valid = self.valid.saturating_sub(bits);
-        self.fill()
+        // refill in batches, `peek` never needs more than 16 bits
+        if self.valid < 16 {
+            self.fill()?;
+        }
+        Ok(())
     }
     fn bits_to_byte_boundary(&self) -> u8 {
         self.valid & 7
     }
 }
 
+/// Pack a string of `0` and `1` into bytes, ignoring spaces.
+#[cfg(test)]
+pub(crate) fn pack_bits(s: &str) -> Vec<u8> {
+    let bits: Vec<u8> = s.bytes().filter(|&b| b != b' ').map(|b| b - b'0').collect();
+    bits.chunks(8)
+        .map(|c| c.iter().enumerate().fold(0, |b, (i, &v)| b | v << (7 - i)))
+        .collect()
+}
+
 #[test]
 fn test_bits() {
     let mut bits = slice_reader(&[0b0000_1101, 0b1010_0000]);
@@ -339,28 +570,20 @@ mod tests {
         let eol: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
 
         for fill_count in 0u8..=7 {
-            let mut stream_bits = Vec::new();
-
             // Initial EOL with fill
-            for _ in 0..fill_count {
-                stream_bits.push(0);
-            }
+            let mut stream_bits = vec![0; fill_count as usize];
             stream_bits.extend_from_slice(eol);
 
             // Line: white(4) = 1011
             stream_bits.extend_from_slice(&[1, 0, 1, 1]);
 
             // EOL with fill
-            for _ in 0..fill_count {
-                stream_bits.push(0);
-            }
+            stream_bits.extend(std::iter::repeat(0).take(fill_count as usize));
             stream_bits.extend_from_slice(eol);
 
             // RTC: 5 more EOLs with fill
             for _ in 0..5 {
-                for _ in 0..fill_count {
-                    stream_bits.push(0);
-                }
+                stream_bits.extend(std::iter::repeat(0).take(fill_count as usize));
                 stream_bits.extend_from_slice(eol);
             }
 
@@ -422,6 +645,65 @@ mod tests {
         assert_eq!(lines[1], vec![8]);
         assert_eq!(lines[2], vec![2, 5]); // white 2, then black 3 = positions 2, 5
     }
+
+    #[test]
+    fn test_group3_2d_lines() {
+        // Width 8, every EOL followed by a 1D/2D tag bit.
+        let mut stream_bits = Vec::new();
+        let eol: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+
+        // Initial EOL, next line is 1D
+        stream_bits.extend_from_slice(eol);
+        stream_bits.push(1);
+
+        // Line 1: white(2)=0111, black(3)=10, white(3)=1000
+        stream_bits.extend_from_slice(&[0, 1, 1, 1, 1, 0, 1, 0, 0, 0]);
+        stream_bits.extend_from_slice(eol);
+        stream_bits.push(0);
+
+        // Line 2: same as line 1, V0 V0 V0
+        stream_bits.extend_from_slice(&[1, 1, 1]);
+        stream_bits.extend_from_slice(eol);
+        stream_bits.push(0);
+
+        // Line 3: all white, Pass=0001 then V0
+        stream_bits.extend_from_slice(&[0, 0, 0, 1, 1]);
+
+        // RTC: 6 times EOL + 1
+        for _ in 0..6 {
+            stream_bits.extend_from_slice(eol);
+            stream_bits.push(1);
+        }
+
+        let data = bits_to_bytes(&stream_bits);
+        let mut lines = Vec::new();
+        let result = decoder::decode_g3_2d(data.into_iter(), 8, |transitions| {
+            lines.push(transitions.to_vec());
+        });
+
+        assert_eq!(result, Some(()));
+        assert_eq!(lines, vec![vec![2, 5], vec![2, 5], vec![]]);
+    }
+
+    #[test]
+    fn test_io_writer_matches_vec_writer() {
+        let lines: [&[u32]; 3] = [&[2, 5], &[], &[0, 1, 7]];
+        let pels = |line: &[u32]| decoder::pels(line, 8).collect::<Vec<_>>();
+
+        let mut encoder = encoder::Encoder::new(VecWriter::new());
+        for line in lines {
+            encoder.encode_line(pels(line).into_iter(), 8).unwrap();
+        }
+        let expected = encoder.finish().unwrap().finish();
+
+        // a tiny buffer forces flushes in the middle of the stream
+        let mut encoder = encoder::Encoder::new(IoWriter::with_capacity(1, Vec::new()));
+        for line in lines {
+            encoder.encode_line(pels(line).into_iter(), 8).unwrap();
+        }
+        let data = encoder.finish().unwrap().finish().unwrap();
+        assert_eq!(data, expected);
+    }
 }
 
 /// Enum used to signal black/white.
@@ -440,6 +722,33 @@ impl Not for Color {
     }
 }
 
+/// How colors map to bits in a bitmap.
+///
+/// The names follow the TIFF `PhotometricInterpretation` values.
+/// PDF `/BlackIs1 true` is `WhiteIsZero`, the default `/BlackIs1 false` is `BlackIsZero`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Polarity {
+    /// White pixels are `0` bits, black pixels are `1` bits.
+    #[default]
+    WhiteIsZero,
+    /// Black pixels are `0` bits, white pixels are `1` bits.
+    BlackIsZero,
+}
+impl Polarity {
+    /// The bit for the given color.
+    pub fn bit(self, color: Color) -> bool {
+        (color == Color::Black) == (self == Polarity::WhiteIsZero)
+    }
+    /// The color of the given bit.
+    pub fn color(self, bit: bool) -> Color {
+        if bit == (self == Polarity::WhiteIsZero) {
+            Color::Black
+        } else {
+            Color::White
+        }
+    }
+}
+
 struct Transitions<'a> {
     edges: &'a [u32],
     pos: usize,
@@ -462,7 +771,7 @@ impl<'a> Transitions<'a> {
         if start_of_row {
             if color == Color::Black {
                 self.pos = 1;
-                return self.edges.get(0).cloned();
+                return self.edges.first().cloned();
             } else {
                 self.pos = 2;
                 return self.edges.get(1).cloned();
@@ -0,0 +1,106 @@
+//! Single-lookup decoding of the common short codes.
+//!
+//! The next few bits index a flat table, which resolves every code up to
+//! that length at once. Longer codes, and the end of the input, fall back to
+//! the prefix tables in `maps`.
+use std::sync::OnceLock;
+
+use crate::maps::{black as black_map, mode as mode_map, white as white_map, Mode};
+use crate::{BitReader, Bits};
+
+struct Table<T> {
+    bits: u8,
+    entries: Vec<Option<(T, u8)>>,
+}
+impl<T: Copy> Table<T> {
+    fn build(bits: u8, codes: &[(T, Bits)]) -> Self {
+        let mut entries = vec![None; 1 << bits];
+        for &(value, code) in codes {
+            if code.len <= bits {
+                let shift = bits - code.len;
+                let start = (code.data as usize) << shift;
+                entries[start..start + (1 << shift)].fill(Some((value, code.len)));
+            }
+        }
+        Table { bits, entries }
+    }
+    fn decode<R: BitReader>(&self, reader: &mut R, fallback: fn(&mut R) -> Option<T>) -> Option<T> {
+        if let Some(index) = reader.peek(self.bits) {
+            if let Some((value, len)) = self.entries[index as usize] {
+                reader.consume(len).ok()?;
+                return Some(value);
+            }
+        }
+        fallback(reader)
+    }
+}
+
+pub fn mode<R: BitReader>(reader: &mut R) -> Option<Mode> {
+    static TABLE: OnceLock<Table<Mode>> = OnceLock::new();
+    TABLE
+        .get_or_init(|| Table::build(7, &mode_map::ENTRIES))
+        .decode(reader, mode_map::decode)
+}
+
+pub fn white<R: BitReader>(reader: &mut R) -> Option<u16> {
+    static TABLE: OnceLock<Table<u16>> = OnceLock::new();
+    TABLE
+        .get_or_init(|| Table::build(9, &white_map::ENTRIES))
+        .decode(reader, white_map::decode)
+}
+
+pub fn black<R: BitReader>(reader: &mut R) -> Option<u16> {
+    static TABLE: OnceLock<Table<u16>> = OnceLock::new();
+    TABLE
+        .get_or_init(|| Table::build(10, &black_map::ENTRIES))
+        .decode(reader, black_map::decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{slice_reader, BitWriter, VecWriter};
+
+    /// `code`, followed by a marker and optionally enough data for a full lookup.
+    fn encoded(code: Bits, tail: bool) -> Vec<u8> {
+        let mut writer = VecWriter::new();
+        writer.write(code).unwrap();
+        writer
+            .write(Bits {
+                data: 0b1011,
+                len: 4,
+            })
+            .unwrap();
+        let mut data = writer.finish();
+        if tail {
+            data.extend([0, 0]);
+        }
+        data
+    }
+
+    /// Every code decodes to its value, with and without enough input for the lookup table.
+    #[test]
+    fn all_codes() {
+        for tail in [false, true] {
+            for &(value, code) in mode_map::ENTRIES.iter() {
+                let data = encoded(code, tail);
+                let mut reader = slice_reader(&data);
+                let mode = mode(&mut reader).map(|m| format!("{:?}", m));
+                assert_eq!(mode, Some(format!("{:?}", value)));
+                assert_eq!(reader.peek(4), Some(0b1011));
+            }
+            for &(value, code) in white_map::ENTRIES.iter() {
+                let data = encoded(code, tail);
+                let mut reader = slice_reader(&data);
+                assert_eq!(white(&mut reader), Some(value));
+                assert_eq!(reader.peek(4), Some(0b1011));
+            }
+            for &(value, code) in black_map::ENTRIES.iter() {
+                let data = encoded(code, tail);
+                let mut reader = slice_reader(&data);
+                assert_eq!(black(&mut reader), Some(value));
+                assert_eq!(reader.peek(4), Some(0b1011));
+            }
+        }
+    }
+}
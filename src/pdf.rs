@@ -0,0 +1,642 @@
+use std::convert::Infallible;
+use std::io::{self, Write};
+
+use crate::decoder::{pack_row, DecodeStatus, Group3Decoder, Group4Decoder};
+use crate::tiff::{self, Compression};
+use crate::{FillOrder, Polarity};
+
+/// Parameters of the PDF `CCITTFaxDecode` filter, as found in `/DecodeParms`.
+///
+/// `CcittParams::default()` has the default values from the PDF specification.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CcittParams {
+    /// `/K`: negative for Group 4, `0` for one-dimensional Group 3
+    /// and positive for mixed one- and two-dimensional Group 3.
+    pub k: i32,
+    /// `/EndOfLine`: every line has to be terminated by an EOL.
+    pub end_of_line: bool,
+    /// `/EncodedByteAlign`: every line starts at a byte boundary.
+    pub encoded_byte_align: bool,
+    /// `/Columns`: width of the image in pixels.
+    pub columns: u32,
+    /// `/Rows`: height of the image in pixels, `0` if unknown.
+    pub rows: u32,
+    /// `/EndOfBlock`: the data is terminated by an end-of-block marker (RTC or EOFB).
+    pub end_of_block: bool,
+    /// `/BlackIs1`: black pixels are `1` bits in the decoded data.
+    pub black_is_1: bool,
+    /// `/DamagedRowsBeforeError`: number of damaged rows tolerated in Group 3 data.
+    pub damaged_rows_before_error: u32,
+}
+impl CcittParams {
+    /// The bit polarity of the decoded data, according to `black_is_1`.
+    pub fn polarity(&self) -> Polarity {
+        if self.black_is_1 {
+            Polarity::WhiteIsZero
+        } else {
+            Polarity::BlackIsZero
+        }
+    }
+}
+impl Default for CcittParams {
+    fn default() -> Self {
+        CcittParams {
+            k: 0,
+            end_of_line: false,
+            encoded_byte_align: false,
+            columns: 1728,
+            rows: 0,
+            end_of_block: true,
+            black_is_1: false,
+            damaged_rows_before_error: 0,
+        }
+    }
+}
+
+/// Decode the data of a `CCITTFaxDecode` stream.
+///
+/// `params.k` selects the Group 3 or Group 4 decoder. The result contains
+/// `params.columns` pixels per row, packed into whole bytes. Like the PDF filter,
+/// `params.black_is_1` selects whether black pixels are `1` or `0` bits.
+///
+/// If `params.rows` is given, exactly that many rows are returned.
+/// Rows missing at the end of the data are filled with white.
+pub fn decode(input: impl Iterator<Item = u8>, params: &CcittParams) -> Option<Vec<u8>> {
+    let reader = input.map(Result::<u8, Infallible>::Ok);
+    let width = params.columns;
+    let max_rows = if params.rows > 0 {
+        params.rows
+    } else {
+        u32::MAX
+    };
+    let polarity = params.polarity();
+
+    let mut out = Vec::new();
+    let mut rows = 0;
+    if params.k < 0 {
+        let mut decoder = Group4Decoder::new(reader, width)
+            .ok()?
+            .with_byte_align(params.encoded_byte_align)
+            .with_end_of_block(params.end_of_block);
+        while rows < max_rows {
+            if decoder.advance().ok()? == DecodeStatus::End {
+                break;
+            }
+            push_row(&mut out, decoder.transition(), width, polarity);
+            rows += 1;
+        }
+    } else {
+        let mut decoder = Group3Decoder::with_params(reader, params).ok()?;
+        if params.end_of_line {
+            decoder = decoder.with_recovery(params.damaged_rows_before_error);
+        }
+        while rows < max_rows {
+            let status = decoder.advance().ok()?;
+            push_row(&mut out, decoder.transitions(), width, polarity);
+            rows += 1;
+            if status == DecodeStatus::End {
+                break;
+            }
+        }
+    }
+
+    while rows < params.rows {
+        push_row(&mut out, &[], width, polarity);
+        rows += 1;
+    }
+    Some(out)
+}
+
+/// Append one row of pixels, padded to a whole byte.
+fn push_row(out: &mut Vec<u8>, transitions: &[u32], width: u32, polarity: Polarity) {
+    let start = out.len();
+    out.resize(start + (width as usize + 7) / 8, 0);
+    pack_row(transitions, width, polarity, &mut out[start..]);
+}
+
+/// Writes a multi-page PDF of CCITT compressed images.
+///
+/// The compressed data is embedded as it is, in an image XObject with the
+/// `CCITTFaxDecode` filter. Pages are streamed to the writer as they are added,
+/// the page tree and the cross-reference table are written by `finish`.
+pub struct PdfWriter<W: Write> {
+    write: W,
+    pos: u64,
+    /// Position of every object, the object number is the index + 1
+    offsets: Vec<u64>,
+    /// Object numbers of the pages
+    pages: Vec<usize>,
+}
+impl<W: Write> PdfWriter<W> {
+    /// Object numbers of the catalog and the page tree, written last.
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+
+    pub fn new(write: W) -> Self {
+        PdfWriter {
+            write,
+            pos: 0,
+            offsets: vec![0; Self::PAGES],
+            pages: Vec::new(),
+        }
+    }
+
+    /// Add a page showing the image in `data`, which is described by `params`.
+    ///
+    /// `params.rows` has to be set. The page has the size of the image
+    /// at the given resolution in dots per inch.
+    pub fn write_page(
+        &mut self,
+        data: &[u8],
+        params: &CcittParams,
+        x_resolution: u32,
+        y_resolution: u32,
+    ) -> io::Result<()> {
+        self.write_images(&[(data, *params)], x_resolution, y_resolution)
+    }
+
+    /// Add a TIFF page that is not tiled.
+    ///
+    /// Every strip becomes an image of its own, they are stacked on the page from the top.
+    /// The data is only bit-reversed for `FillOrder::LsbFirst`, which PDF does not know.
+    pub fn write_tiff_page(&mut self, page: &tiff::Page) -> io::Result<()> {
+        let rows_per_strip = page.rows_per_strip as u64;
+        let strips = match rows_per_strip {
+            0 => 0,
+            _ => (page.height as u64 + rows_per_strip - 1) / rows_per_strip,
+        };
+        if page.tile_size.is_some() || page.strips.len() as u64 != strips {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only pages in strips that cover the page can be embedded",
+            ));
+        }
+        let mut params = CcittParams {
+            columns: page.width,
+            // with `BlackIsZero`, the white runs of the code are shown black
+            black_is_1: page.polarity == Polarity::BlackIsZero,
+            // the data may end without a marker
+            end_of_block: false,
+            ..CcittParams::default()
+        };
+        match page.compression {
+            // rows without EOLs, starting on a byte boundary
+            Compression::Rle => params.encoded_byte_align = true,
+            Compression::Group3 { t4_options } => {
+                params.k = if t4_options & tiff::T4_2D != 0 { 1 } else { 0 };
+                // fill bits are zeros in front of an EOL, which readers skip anyway
+                params.end_of_line = true;
+            }
+            Compression::Group4 { .. } => params.k = -1,
+        }
+
+        let reversed: Vec<Vec<u8>>;
+        let strips: Vec<&[u8]> = match page.fill_order {
+            FillOrder::MsbFirst => page.strips.iter().map(|s| &s[..]).collect(),
+            FillOrder::LsbFirst => {
+                reversed = page
+                    .strips
+                    .iter()
+                    .map(|s| s.iter().map(|b| b.reverse_bits()).collect())
+                    .collect();
+                reversed.iter().map(|s| &s[..]).collect()
+            }
+        };
+        let mut images = Vec::with_capacity(strips.len());
+        let mut top = 0;
+        for data in strips {
+            // the last strip may be shorter
+            let rows = page.rows_per_strip.min(page.height - top);
+            images.push((data, CcittParams { rows, ..params }));
+            top += rows;
+        }
+        self.write_images(&images, page.x_resolution, page.y_resolution)
+    }
+
+    /// Write the page tree, the catalog and the cross-reference table and return the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.pages.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a PDF needs at least one page",
+            ));
+        }
+        let kids: Vec<String> = self.pages.iter().map(|p| format!("{} 0 R", p)).collect();
+        let pages = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            kids.len()
+        );
+        self.object(Self::PAGES, &pages)?;
+        let catalog = format!("<< /Type /Catalog /Pages {} 0 R >>", Self::PAGES);
+        self.object(Self::CATALOG, &catalog)?;
+
+        let xref = self.pos;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for pos in &self.offsets {
+            table += &format!("{:010} 00000 n \n", pos);
+        }
+        table += &format!(
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            Self::CATALOG,
+            xref
+        );
+        self.put(table.as_bytes())?;
+        self.write.flush()?;
+        Ok(self.write)
+    }
+
+    /// Add a page showing `images` of the same width, stacked from the top.
+    fn write_images(
+        &mut self,
+        images: &[(&[u8], CcittParams)],
+        x_resolution: u32,
+        y_resolution: u32,
+    ) -> io::Result<()> {
+        let columns = images.first().map_or(0, |(_, params)| params.columns);
+        if columns == 0
+            || images
+                .iter()
+                .any(|(_, params)| params.columns != columns || params.rows == 0)
+            || x_resolution == 0
+            || y_resolution == 0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the image size and resolution are required",
+            ));
+        }
+        if self.pos == 0 {
+            // the comment marks the file as binary
+            self.put(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+        }
+
+        let first = self.offsets.len() + 1;
+        let contents = first + images.len();
+        let page = contents + 1;
+
+        // points are 1/72 inch
+        let x_scale = 72. / x_resolution as f64;
+        let y_scale = 72. / y_resolution as f64;
+        let width = number(columns as f64 * x_scale);
+        let mut bottom: u64 = images.iter().map(|(_, params)| params.rows as u64).sum();
+        let height = number(bottom as f64 * y_scale);
+
+        let mut show = Vec::with_capacity(images.len());
+        let mut xobjects = Vec::with_capacity(images.len());
+        for (i, (data, params)) in images.iter().enumerate() {
+            self.image(first + i, data, params)?;
+            // the origin is at the bottom left of the page
+            bottom -= params.rows as u64;
+            show.push(format!(
+                "q {} 0 0 {} 0 {} cm /Im{} Do Q",
+                width,
+                number(params.rows as f64 * y_scale),
+                number(bottom as f64 * y_scale),
+                i
+            ));
+            xobjects.push(format!("/Im{} {} 0 R", i, first + i));
+        }
+        self.stream(contents, "", show.join("\n").as_bytes())?;
+
+        self.object(
+            page,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /XObject << {} >> >> /Contents {} 0 R >>",
+                Self::PAGES,
+                width,
+                height,
+                xobjects.join(" "),
+                contents
+            ),
+        )?;
+        self.pages.push(page);
+        Ok(())
+    }
+    /// Write the image XObject `id`.
+    fn image(&mut self, id: usize, data: &[u8], params: &CcittParams) -> io::Result<()> {
+        let mut parms = format!(
+            "/K {} /Columns {} /Rows {} /BlackIs1 {} /EncodedByteAlign {}",
+            params.k, params.columns, params.rows, params.black_is_1, params.encoded_byte_align
+        );
+        if params.end_of_line {
+            parms.push_str(" /EndOfLine true");
+        }
+        if !params.end_of_block {
+            parms.push_str(" /EndOfBlock false");
+        }
+        if params.damaged_rows_before_error > 0 {
+            parms += &format!(
+                " /DamagedRowsBeforeError {}",
+                params.damaged_rows_before_error
+            );
+        }
+        let dict = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray \
+             /BitsPerComponent 1 /Filter /CCITTFaxDecode /DecodeParms << {} >>",
+            params.columns, params.rows, parms
+        );
+        self.stream(id, &dict, data)
+    }
+
+    fn put(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write.write_all(data)?;
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+    /// Record the position of object `id`, which starts here.
+    fn start(&mut self, id: usize) {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.pos;
+    }
+    fn object(&mut self, id: usize, value: &str) -> io::Result<()> {
+        self.start(id);
+        self.put(format!("{} 0 obj\n{}\nendobj\n", id, value).as_bytes())
+    }
+    /// Write a stream object, `dict` holds the entries besides `/Length`.
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) -> io::Result<()> {
+        self.start(id);
+        let length = format!("/Length {}", data.len());
+        let dict = match dict {
+            "" => length,
+            _ => format!("{} {}", dict, length),
+        };
+        self.put(format!("{} 0 obj\n<< {} >>\nstream\n", id, dict).as_bytes())?;
+        self.put(data)?;
+        self.put(b"\nendstream\nendobj\n")
+    }
+}
+
+/// Format a number of points, with up to three decimal places.
+fn number(value: f64) -> String {
+    let s = format!("{:.3}", value);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::Bitmap;
+    use crate::decoder::pels;
+    use crate::encoder::{Encoder, Group3Encoder, RleEncoder};
+    use crate::VecWriter;
+
+    // 10 pixels per row: black at 0..2 and 8 in the first row, all white in the second.
+    const LINES: [&[u32]; 2] = [&[0, 2, 8, 9], &[]];
+
+    #[test]
+    fn decode_dispatch() {
+        let width = 10;
+        let mut g4 = Encoder::new(VecWriter::new());
+        let mut g4_aligned = Encoder::new(VecWriter::new()).with_byte_align(true);
+        let mut g3 = Group3Encoder::new_2d(VecWriter::new(), 2);
+        let mut rle = RleEncoder::new(VecWriter::new());
+        for line in LINES {
+            g4.encode_line(pels(line, width), width).unwrap();
+            g4_aligned.encode_line(pels(line, width), width).unwrap();
+            g3.encode_line(pels(line, width), width).unwrap();
+            rle.encode_line(pels(line, width), width).unwrap();
+        }
+        let streams = [
+            (g4.finish().unwrap().finish(), -1, false, false),
+            (g4_aligned.finish().unwrap().finish(), -1, false, true),
+            (g3.finish().unwrap().finish(), 2, true, false),
+            (rle.finish().unwrap().finish(), 0, false, true),
+        ];
+        for (data, k, end_of_line, encoded_byte_align) in streams {
+            let mut params = CcittParams {
+                k,
+                end_of_line,
+                encoded_byte_align,
+                columns: width,
+                ..CcittParams::default()
+            };
+            let decoded = decode(data.iter().copied(), &params);
+            assert_eq!(decoded, Some(vec![0x3f, 0x40, 0xff, 0xc0]), "k={}", k);
+
+            params.black_is_1 = true;
+            params.rows = 3;
+            let decoded = decode(data.iter().copied(), &params);
+            assert_eq!(
+                decoded,
+                Some(vec![0xc0, 0x80, 0, 0, 0, 0]),
+                "k={} black_is_1",
+                k
+            );
+        }
+    }
+
+    /// Group 3 data often ends without an RTC.
+    #[test]
+    fn g3_without_rtc() {
+        let eol = "000000000001";
+        // white 0, black 2, white 6, black 1, white 1
+        let first = "00110101 11 1110 010 000111";
+        // white 10
+        let second = "00111";
+        // pass, pass, V0 against the first line
+        let second_2d = "0001 0001 1";
+        let streams = [
+            (0, format!("{eol} {first} {eol} {second}")),
+            (0, format!("{eol} {first} {eol} {second} {eol}")),
+            (1, format!("{eol}1 {first} {eol}0 {second_2d}")),
+        ];
+        for (k, bits) in streams {
+            for end_of_line in [false, true] {
+                let params = CcittParams {
+                    k,
+                    end_of_line,
+                    columns: 10,
+                    ..CcittParams::default()
+                };
+                let data = crate::pack_bits(&bits);
+                let decoded = decode(data.iter().copied(), &params);
+                assert_eq!(
+                    decoded,
+                    Some(vec![0x3f, 0x40, 0xff, 0xc0]),
+                    "{} {}",
+                    bits,
+                    end_of_line
+                );
+            }
+        }
+    }
+
+    /// Without `/EndOfBlock`, only `/Rows` ends the data.
+    #[test]
+    fn end_of_block() {
+        let width = 10;
+        let mut g4 = Encoder::new(VecWriter::new());
+        let mut g3 = Group3Encoder::new(VecWriter::new());
+        for line in LINES {
+            g4.encode_line(pels(line, width), width).unwrap();
+            g3.encode_line(pels(line, width), width).unwrap();
+        }
+        let g4 = g4.finish().unwrap().finish();
+        let g3 = g3.finish().unwrap().finish();
+
+        for (data, k) in [(g4, -1), (g3, 0)] {
+            let mut params = CcittParams {
+                k,
+                columns: width,
+                rows: 2,
+                end_of_block: false,
+                ..CcittParams::default()
+            };
+            let decoded = decode(data.iter().copied(), &params);
+            assert_eq!(decoded, Some(vec![0x3f, 0x40, 0xff, 0xc0]), "k={}", k);
+
+            // a third row runs into the EOFB or RTC
+            params.rows = 3;
+            let padded = Some(vec![0x3f, 0x40, 0xff, 0xc0, 0xff, 0xc0]);
+            let decoded = decode(data.iter().copied(), &params);
+            if k < 0 {
+                // an EOFB is not valid data
+                assert_eq!(decoded, None);
+            } else {
+                // the EOLs of the RTC are read as empty lines
+                assert_eq!(decoded, padded);
+            }
+            // with `/EndOfBlock`, the missing row is white
+            params.end_of_block = true;
+            let decoded = decode(data.iter().copied(), &params);
+            assert_eq!(decoded, padded, "k={}", k);
+        }
+    }
+
+    /// The text of object `id`, found through the cross-reference table.
+    fn object(pdf: &[u8], id: usize) -> &[u8] {
+        let trailer = pdf.windows(9).rposition(|w| w == b"startxref").unwrap();
+        let trailer = std::str::from_utf8(&pdf[trailer..]).unwrap();
+        let xref: usize = trailer.lines().nth(1).unwrap().parse().unwrap();
+        let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+        assert!(table.starts_with("xref\n0 "));
+        let start: usize = table.lines().nth(2 + id).unwrap()[..10].parse().unwrap();
+        let len = pdf[start..]
+            .windows(6)
+            .position(|w| w == b"endobj")
+            .unwrap();
+        let object = &pdf[start..start + len];
+        assert!(object.starts_with(format!("{} 0 obj\n", id).as_bytes()));
+        object
+    }
+    fn stream(object: &[u8]) -> &[u8] {
+        let text = String::from_utf8_lossy(object);
+        let length = text.split("/Length ").nth(1).unwrap();
+        let length: usize = length.split(' ').next().unwrap().parse().unwrap();
+        let start = text.find(">>\nstream\n").unwrap() + 10;
+        &object[start..start + length]
+    }
+
+    #[test]
+    fn writer() {
+        let width = 10;
+        let mut g4 = Encoder::new(VecWriter::new());
+        let mut g3 = Group3Encoder::new_2d(VecWriter::new(), 2);
+        for line in LINES {
+            g4.encode_line(pels(line, width), width).unwrap();
+            g3.encode_line(pels(line, width), width).unwrap();
+        }
+        let g4 = g4.finish().unwrap().finish();
+        let g3 = g3.finish().unwrap().finish();
+        let params = CcittParams {
+            k: -1,
+            columns: width,
+            rows: 2,
+            black_is_1: true,
+            ..CcittParams::default()
+        };
+        let mut page = tiff::Page::new(
+            width,
+            2,
+            Compression::Group3 {
+                t4_options: tiff::T4_2D,
+            },
+            g3.iter().map(|b| b.reverse_bits()).collect(),
+        );
+        page.fill_order = FillOrder::LsbFirst;
+        page.polarity = Polarity::BlackIsZero;
+        page.y_resolution = 100;
+
+        let mut writer = PdfWriter::new(Vec::new());
+        writer.write_page(&g4, &params, 200, 200).unwrap();
+        writer.write_tiff_page(&page).unwrap();
+        assert!(writer
+            .write_page(&g4, &CcittParams::default(), 200, 200)
+            .is_err());
+        page.strips.push(vec![]);
+        assert!(writer.write_tiff_page(&page).is_err());
+
+        // strips of two rows
+        let mut bitmap = Bitmap::new(width, 3);
+        for (y, line) in LINES.iter().chain(&LINES[..1]).enumerate() {
+            bitmap.set_transitions(y as u32, line);
+        }
+        let page = tiff::Page::encode(&bitmap, Compression::Group4 { t6_options: 0 }, 2);
+        writer.write_tiff_page(&page).unwrap();
+        let pdf = writer.finish().unwrap();
+
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        let catalog = String::from_utf8_lossy(object(&pdf, 1)).into_owned();
+        assert!(catalog.contains("/Type /Catalog /Pages 2 0 R"));
+        let pages = String::from_utf8_lossy(object(&pdf, 2)).into_owned();
+        assert!(pages.contains("/Kids [5 0 R 8 0 R 12 0 R] /Count 3"));
+        let first = String::from_utf8_lossy(object(&pdf, 5)).into_owned();
+        assert!(first.contains("/MediaBox [0 0 3.6 0.72]"));
+        let second = String::from_utf8_lossy(object(&pdf, 8)).into_owned();
+        assert!(second.contains("/MediaBox [0 0 3.6 1.44]"));
+        assert_eq!(stream(object(&pdf, 4)), b"q 3.6 0 0 0.72 0 0 cm /Im0 Do Q");
+
+        // both images show the same pixels
+        let expected = Some(vec![0xc0, 0x80, 0, 0]);
+        let image = object(&pdf, 3);
+        assert!(String::from_utf8_lossy(image).contains(
+            "/DecodeParms << /K -1 /Columns 10 /Rows 2 /BlackIs1 true /EncodedByteAlign false >>"
+        ));
+        assert_eq!(stream(image), &g4[..]);
+        assert_eq!(decode(stream(image).iter().copied(), &params), expected);
+
+        let image = object(&pdf, 6);
+        let params = CcittParams {
+            k: 1,
+            end_of_line: true,
+            end_of_block: false,
+            ..params
+        };
+        assert!(String::from_utf8_lossy(image).contains(
+            "/K 1 /Columns 10 /Rows 2 /BlackIs1 true /EncodedByteAlign false /EndOfLine true /EndOfBlock false"
+        ));
+        assert_eq!(stream(image), &g3[..]);
+        assert_eq!(decode(stream(image).iter().copied(), &params), expected);
+
+        let third = String::from_utf8_lossy(object(&pdf, 12)).into_owned();
+        assert!(third.contains("/MediaBox [0 0 3.6 1.08]"));
+        assert!(third.contains("/XObject << /Im0 9 0 R /Im1 10 0 R >>"));
+        assert_eq!(
+            stream(object(&pdf, 11)),
+            b"q 3.6 0 0 0.72 0 0.36 cm /Im0 Do Q\nq 3.6 0 0 0.36 0 0 cm /Im1 Do Q"
+        );
+        let params = CcittParams {
+            k: -1,
+            columns: width,
+            end_of_block: false,
+            ..CcittParams::default()
+        };
+        for (id, rows, expected) in [
+            (9, 2, vec![0x3f, 0x40, 0xff, 0xc0]),
+            (10, 1, vec![0x3f, 0x40]),
+        ] {
+            let image = object(&pdf, id);
+            let params = CcittParams { rows, ..params };
+            assert_eq!(
+                decode(stream(image).iter().copied(), &params),
+                Some(expected)
+            );
+        }
+    }
+}
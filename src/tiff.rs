@@ -1,72 +1,1235 @@
-#[derive(Copy, Clone)]
+use std::convert::{Infallible, TryFrom};
+use std::io::{self, Write};
+
+use crate::bitmap::Bitmap;
+use crate::decoder::{pack_row, DecodeStatus, Group3Decoder, Group4Decoder, RleDecoder};
+use crate::encoder::{Encoder, Group3Encoder, RleEncoder};
+use crate::pdf::CcittParams;
+use crate::{FillOrder, Polarity, VecWriter};
+
+/// `T4Options` bit: the data contains two-dimensional (Modified READ) lines
+pub const T4_2D: u32 = 1;
+/// `T4Options` bit: uncompressed mode may be used
+pub const T4_UNCOMPRESSED: u32 = 2;
+/// `T4Options` bit: EOLs are padded with fill bits to end on a byte boundary
+pub const T4_FILL_BITS: u32 = 4;
+/// `T6Options` bit: uncompressed mode may be used
+pub const T6_UNCOMPRESSED: u32 = 2;
+
+/// CCITT compression of a TIFF page.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Modified Huffman with byte aligned rows (2)
+    Rle,
+    /// Group 3 / T.4 (3)
+    Group3 { t4_options: u32 },
+    /// Group 4 / T.6 (4)
+    Group4 { t6_options: u32 },
+}
+impl Compression {
+    fn tag_value(self) -> u16 {
+        match self {
+            Compression::Rle => 2,
+            Compression::Group3 { .. } => 3,
+            Compression::Group4 { .. } => 4,
+        }
+    }
+}
+
+/// One page of a TIFF file, holding the compressed data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Page {
+    pub width: u32,
+    pub height: u32,
+    pub compression: Compression,
+    pub fill_order: FillOrder,
+    /// `PhotometricInterpretation`
+    pub polarity: Polarity,
+    /// horizontal resolution in dots per inch
+    pub x_resolution: u32,
+    /// vertical resolution in dots per inch
+    pub y_resolution: u32,
+    /// `DateTime` as "YYYY:MM:DD HH:MM:SS"
+    pub date_time: Option<String>,
+    pub software: Option<String>,
+    pub description: Option<String>,
+    pub rows_per_strip: u32,
+    pub strips: Vec<Vec<u8>>,
+    /// Width and length of the tiles, `None` if the page is stored in strips.
+    ///
+    /// The tiles are held in `strips`, row by row, and `rows_per_strip` is not used.
+    pub tile_size: Option<(u32, u32)>,
+}
+impl Page {
+    /// A page stored in a single strip, at 200 dpi.
+    pub fn new(width: u32, height: u32, compression: Compression, data: Vec<u8>) -> Self {
+        Page {
+            width,
+            height,
+            compression,
+            fill_order: FillOrder::MsbFirst,
+            polarity: Polarity::WhiteIsZero,
+            x_resolution: 200,
+            y_resolution: 200,
+            date_time: None,
+            software: None,
+            description: None,
+            rows_per_strip: height,
+            strips: vec![data],
+            tile_size: None,
+        }
+    }
+    /// Encode `bitmap` into strips of `rows_per_strip` rows.
+    ///
+    /// Every strip is a stream of its own, starting from a white reference line,
+    /// so that it can be decoded without the ones before it.
+    /// Two-dimensional Group 3 data is written with `k = 4`, and without fill bits.
+    pub fn encode(bitmap: &Bitmap, compression: Compression, rows_per_strip: u32) -> Self {
+        let (width, height) = (bitmap.width(), bitmap.height());
+        let rows_per_strip = rows_per_strip.clamp(1, height.max(1));
+        let strips = (0..height)
+            .step_by(rows_per_strip as usize)
+            .map(|y0| {
+                let rows = y0..(y0 + rows_per_strip).min(height);
+                match compression {
+                    Compression::Rle => {
+                        let mut encoder = RleEncoder::new(VecWriter::new());
+                        for y in rows {
+                            encoder.encode_line(bitmap.pels(y), width).unwrap();
+                        }
+                        encoder.finish().unwrap().finish()
+                    }
+                    Compression::Group3 { t4_options } => {
+                        let mut encoder = if t4_options & T4_2D != 0 {
+                            Group3Encoder::new_2d(VecWriter::new(), 4)
+                        } else {
+                            Group3Encoder::new(VecWriter::new())
+                        };
+                        for y in rows {
+                            encoder.encode_line(bitmap.pels(y), width).unwrap();
+                        }
+                        encoder.finish().unwrap().finish()
+                    }
+                    Compression::Group4 { t6_options } => {
+                        let mut encoder = Encoder::new(VecWriter::new())
+                            .with_uncompressed_mode(t6_options & T6_UNCOMPRESSED != 0);
+                        for y in rows {
+                            encoder.encode_row(bitmap.row(y), width).unwrap();
+                        }
+                        encoder.finish().unwrap().finish()
+                    }
+                }
+            })
+            .collect();
+
+        let compression = match compression {
+            Compression::Group3 { t4_options } => Compression::Group3 {
+                t4_options: t4_options & !T4_FILL_BITS,
+            },
+            compression => compression,
+        };
+        let mut page = Page::new(width, height, compression, vec![]);
+        page.rows_per_strip = rows_per_strip;
+        page.strips = strips;
+        page
+    }
+    /// Encode `bitmap` into Group 4 compressed tiles.
+    ///
+    /// Every tile is encoded on its own, tiles reaching past the edge of the image
+    /// are padded with white. Returns `None` unless the tile size is a multiple of 16.
+    pub fn g4_tiles(bitmap: &Bitmap, tile_width: u32, tile_length: u32) -> Option<Self> {
+        let (width, height) = (bitmap.width(), bitmap.height());
+        tile_count(width, height, tile_width, tile_length)?;
+        let mut tiles = Vec::new();
+        let mut tile_row = vec![0; tile_width as usize / 8];
+        for y0 in (0..height).step_by(tile_length as usize) {
+            for x0 in (0..width).step_by(tile_width as usize) {
+                let mut encoder = Encoder::new(VecWriter::new());
+                for y in y0..y0 + tile_length {
+                    tile_row.fill(0);
+                    if y < height {
+                        let row = &bitmap.row(y)[x0 as usize / 8..];
+                        let len = row.len().min(tile_row.len());
+                        tile_row[..len].copy_from_slice(&row[..len]);
+                    }
+                    encoder.encode_row(&tile_row, tile_width).unwrap();
+                }
+                tiles.push(encoder.finish().unwrap().finish());
+            }
+        }
+
+        let mut page = Page::new(width, height, Compression::Group4 { t6_options: 0 }, vec![]);
+        page.strips = tiles;
+        page.tile_size = Some((tile_width, tile_length));
+        Some(page)
+    }
+
+    /// Decode the page into a bitmap.
+    ///
+    /// Every strip is decoded on its own, rows missing at the end of a strip are white.
+    /// The bitmap shows the page as displayed, so it is inverted for `BlackIsZero`.
+    /// Returns `None` for pages larger than the data can hold, at one bit per row,
+    /// or larger than 2^32 pixels.
+    pub fn decode(&self) -> Option<Bitmap> {
+        let data: u64 = self.strips.iter().map(|s| s.len() as u64).sum();
+        if self.height as u64 > 8 * data || self.width as u64 * self.height as u64 > MAX_PIXELS {
+            return None;
+        }
+        if let Some((tile_width, tile_length)) = self.tile_size {
+            return self.decode_tiles(tile_width, tile_length);
+        }
+        let mut bitmap = Bitmap::new(self.width, 0);
+        let rows_per_strip = self.rows_per_strip.max(1);
+        for strip in &self.strips {
+            let first = bitmap.height();
+            if first >= self.height {
+                break;
+            }
+            let rows = rows_per_strip.min(self.height - first);
+            self.decode_strip(strip, self.width, rows, |transitions| {
+                push_row(&mut bitmap, transitions, self.polarity);
+            })?;
+            while bitmap.height() < first + rows {
+                bitmap.push_transitions(&[]);
+            }
+        }
+        while bitmap.height() < self.height {
+            bitmap.push_transitions(&[]);
+        }
+        Some(bitmap)
+    }
+
+    /// Decode every tile at the tile width and copy it into place.
+    ///
+    /// Fails if the tile size is invalid or tiles are missing.
+    fn decode_tiles(&self, tile_width: u32, tile_length: u32) -> Option<Bitmap> {
+        let tiles = tile_count(self.width, self.height, tile_width, tile_length)?;
+        if (self.strips.len() as u64) < tiles {
+            return None;
+        }
+        let mut bitmap = Bitmap::new(self.width, self.height);
+        let tiles_across = (self.width as u64 + tile_width as u64 - 1) / tile_width as u64;
+        let mut tile_row = vec![0; tile_width as usize / 8];
+        for (i, tile) in self.strips.iter().take(tiles as usize).enumerate() {
+            // both are inside the page
+            let x0 = ((i as u64 % tiles_across) * tile_width as u64) as u32;
+            let y0 = ((i as u64 / tiles_across) * tile_length as u64) as u32;
+            let mut y = y0;
+            let rows = tile_length.min(self.height - y0);
+            self.decode_strip(tile, tile_width, rows, |transitions| {
+                pack_row(transitions, tile_width, self.polarity, &mut tile_row);
+                let row = &mut bitmap.row_mut(y)[x0 as usize / 8..];
+                let len = row.len().min(tile_row.len());
+                row[..len].copy_from_slice(&tile_row[..len]);
+                y += 1;
+            })?;
+        }
+        // clear the pixels of the last tiles past the edge of the image
+        if self.width % 8 != 0 {
+            let mask = !(0xff >> (self.width % 8));
+            for y in 0..self.height {
+                if let Some(last) = bitmap.row_mut(y).last_mut() {
+                    *last &= mask;
+                }
+            }
+        }
+        Some(bitmap)
+    }
+
+    /// Decode up to `rows` lines of one strip or tile.
+    fn decode_strip(
+        &self,
+        strip: &[u8],
+        width: u32,
+        rows: u32,
+        mut line_cb: impl FnMut(&[u32]),
+    ) -> Option<()> {
+        let reader = strip.iter().map(|&b| Ok::<u8, Infallible>(b));
+        match self.compression {
+            Compression::Rle => {
+                let mut decoder = RleDecoder::new(reader, width)
+                    .ok()?
+                    .with_fill_order(self.fill_order);
+                for _ in 0..rows {
+                    if decoder.advance().ok()? == DecodeStatus::End {
+                        break;
+                    }
+                    line_cb(decoder.transitions());
+                }
+            }
+            Compression::Group3 { t4_options } => {
+                let params = CcittParams {
+                    k: (t4_options & T4_2D) as i32,
+                    columns: width,
+                    rows,
+                    ..CcittParams::default()
+                };
+                let mut decoder = Group3Decoder::with_params(reader, &params)
+                    .ok()?
+                    .with_fill_order(self.fill_order);
+                for _ in 0..rows {
+                    let status = decoder.advance().ok()?;
+                    line_cb(decoder.transitions());
+                    if status == DecodeStatus::End {
+                        break;
+                    }
+                }
+            }
+            Compression::Group4 { .. } => {
+                let mut decoder = Group4Decoder::new(reader, width)
+                    .ok()?
+                    .with_fill_order(self.fill_order);
+                for _ in 0..rows {
+                    if decoder.advance().ok()? == DecodeStatus::End {
+                        break;
+                    }
+                    line_cb(decoder.transition());
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// Pages with more pixels are not decoded, their bitmap would take 512 MiB.
+const MAX_PIXELS: u64 = 1 << 32;
+
+/// Number of tiles covering a page.
+///
+/// `None` unless the tiles are a multiple of 16 in both directions, and at most `MAX_PIXELS`.
+fn tile_count(width: u32, height: u32, tile_width: u32, tile_length: u32) -> Option<u64> {
+    let valid = tile_width > 0
+        && tile_width % 16 == 0
+        && tile_length > 0
+        && tile_length % 16 == 0
+        && tile_width as u64 * tile_length as u64 <= MAX_PIXELS;
+    if !valid {
+        return None;
+    }
+    let tiles = |size: u32, tile: u32| (size as u64 + tile as u64 - 1) / tile as u64;
+    Some(tiles(width, tile_width) * tiles(height, tile_length))
+}
+
+/// Append a row to `bitmap`, packed in the given polarity.
+fn push_row(bitmap: &mut Bitmap, transitions: &[u32], polarity: Polarity) {
+    bitmap.push_transitions(&[]);
+    let (width, y) = (bitmap.width(), bitmap.height() - 1);
+    pack_row(transitions, width, polarity, bitmap.row_mut(y));
+}
+
 enum Value {
-    Short(u16),
-    Long(u32),
-    Rational(u32, u32),
-    DataOffset,
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    Ascii(String),
+    /// Positions and sizes in the file, LONG8 in BigTIFF and LONG otherwise
+    Long8(Vec<u64>),
+}
+
+/// Layout of the file, classic TIFF or BigTIFF
+struct Format {
+    /// BigTIFF: 64-bit offsets and counts
+    big: bool,
+}
+impl Format {
+    fn header_len(&self) -> u64 {
+        if self.big {
+            16
+        } else {
+            8
+        }
+    }
+    /// Size of the number of entries at the start of an IFD.
+    fn count_len(&self) -> usize {
+        if self.big {
+            8
+        } else {
+            2
+        }
+    }
+    fn entry_len(&self) -> usize {
+        if self.big {
+            20
+        } else {
+            12
+        }
+    }
+    /// Size of an offset, values up to this size are stored in the entry itself.
+    fn offset_len(&self) -> usize {
+        if self.big {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn offset(&self, pos: u64) -> io::Result<Vec<u8>> {
+        if self.big {
+            return Ok(pos.to_le_bytes().to_vec());
+        }
+        u32::try_from(pos)
+            .map(|pos| pos.to_le_bytes().to_vec())
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "TIFF larger than 4 GiB, use BigTIFF",
+                )
+            })
+    }
+    /// Number of values in an entry.
+    fn count(&self, count: usize) -> Vec<u8> {
+        if self.big {
+            (count as u64).to_le_bytes().to_vec()
+        } else {
+            (count as u32).to_le_bytes().to_vec()
+        }
+    }
+    fn header(&self, first_ifd: u64) -> io::Result<Vec<u8>> {
+        let mut out = if self.big {
+            // version, size of offsets, reserved
+            vec![b'I', b'I', 43, 0, 8, 0, 0, 0]
+        } else {
+            vec![b'I', b'I', 42, 0]
+        };
+        out.extend_from_slice(&self.offset(first_ifd)?);
+        Ok(out)
+    }
+    /// Type and little endian bytes of a value.
+    fn encode(&self, value: &Value) -> io::Result<(u16, usize, Vec<u8>)> {
+        let mut out = Vec::new();
+        let (typ, count) = match value {
+            Value::Short(v) => {
+                v.iter()
+                    .for_each(|n| out.extend_from_slice(&n.to_le_bytes()));
+                (3, v.len())
+            }
+            Value::Long(v) => {
+                v.iter()
+                    .for_each(|n| out.extend_from_slice(&n.to_le_bytes()));
+                (4, v.len())
+            }
+            Value::Rational(v) => {
+                for (nom, denom) in v {
+                    out.extend_from_slice(&nom.to_le_bytes());
+                    out.extend_from_slice(&denom.to_le_bytes());
+                }
+                (5, v.len())
+            }
+            Value::Ascii(s) => {
+                out.extend_from_slice(s.as_bytes());
+                out.push(0);
+                (2, out.len())
+            }
+            Value::Long8(v) => {
+                for &pos in v {
+                    out.extend_from_slice(&self.offset(pos)?);
+                }
+                // LONG or LONG8
+                (if self.big { 16 } else { 4 }, v.len())
+            }
+        };
+        Ok((typ, count, out))
+    }
+    /// Serialize an IFD located at `pos`, with the next IFD pointer set to `0`.
+    fn ifd(&self, pos: u64, entries: &[(u16, Value)]) -> io::Result<Vec<u8>> {
+        let ifd_len = self.count_len() + self.entry_len() * entries.len() + self.offset_len();
+        let mut out = Vec::with_capacity(ifd_len);
+        let mut extra = Vec::new();
+
+        if self.big {
+            out.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        } else {
+            out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        }
+        for (tag, value) in entries {
+            let (typ, count, mut bytes) = self.encode(value)?;
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&typ.to_le_bytes());
+            out.extend_from_slice(&self.count(count));
+            if bytes.len() <= self.offset_len() {
+                bytes.resize(self.offset_len(), 0);
+                out.extend_from_slice(&bytes);
+            } else {
+                out.extend_from_slice(&self.offset(pos + (ifd_len + extra.len()) as u64)?);
+                extra.extend_from_slice(&bytes);
+                // values start on a word boundary
+                if extra.len() % 2 == 1 {
+                    extra.push(0);
+                }
+            }
+        }
+        out.resize(ifd_len, 0);
+        out.extend_from_slice(&extra);
+        Ok(out)
+    }
+    /// Set the next IFD pointer of an IFD produced by `ifd`.
+    fn link(&self, ifd: &mut [u8], count: usize, next: u64) -> io::Result<()> {
+        let at = self.count_len() + self.entry_len() * count;
+        ifd[at..at + self.offset_len()].copy_from_slice(&self.offset(next)?);
+        Ok(())
+    }
+}
+
+/// Writes a multi-page TIFF file.
+///
+/// Pages are streamed to the writer as they are added. The IFD of each page
+/// follows its data and is held back until the position of the next one is known.
+pub struct TiffWriter<W: Write> {
+    write: W,
+    format: Format,
+    pos: u64,
+    pages: u32,
+    page_count: u16,
+    /// IFD of the last page and its number of entries
+    pending: Option<(Vec<u8>, usize)>,
+}
+impl<W: Write> TiffWriter<W> {
+    pub fn new(write: W) -> Self {
+        TiffWriter {
+            write,
+            format: Format { big: false },
+            pos: 0,
+            pages: 0,
+            page_count: 0,
+            pending: None,
+        }
+    }
+    /// Total number of pages, written in the `PageNumber` tag.
+    ///
+    /// Defaults to `0`, which means unknown.
+    pub fn with_page_count(mut self, page_count: u16) -> Self {
+        self.page_count = page_count;
+        self
+    }
+    /// Write a BigTIFF file, with 64-bit offsets.
+    ///
+    /// Classic TIFF files are limited to 4 GiB.
+    pub fn with_big_tiff(mut self, big: bool) -> Self {
+        self.format.big = big;
+        self
+    }
+
+    /// Write a page and its data.
+    ///
+    /// Fails if the page is tiled and the tile size is not a multiple of 16
+    /// or the number of tiles does not cover the page, and after 65536 pages,
+    /// which is as many as the `PageNumber` tag can count.
+    pub fn write_page(&mut self, page: &Page) -> io::Result<()> {
+        let page_number = u16::try_from(self.pages)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many pages"))?;
+        if let Some((tile_width, tile_length)) = page.tile_size {
+            let tiles = tile_count(page.width, page.height, tile_width, tile_length);
+            if tiles != Some(page.strips.len() as u64) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid tile size or number of tiles",
+                ));
+            }
+        }
+        let data_len: u64 = page.strips.iter().map(|s| s.len() as u64).sum();
+        // IFDs start on a word boundary
+        let pad = (data_len % 2) as usize;
+
+        match self.pending.take() {
+            None => {
+                let first_ifd = self.format.header_len() + data_len + pad as u64;
+                let header = self.format.header(first_ifd)?;
+                self.put(&header)?;
+            }
+            Some((mut ifd, count)) => {
+                let next = self.pos + (ifd.len() + pad) as u64 + data_len;
+                self.format.link(&mut ifd, count, next)?;
+                self.put(&ifd)?;
+            }
+        }
+
+        let mut offsets = Vec::with_capacity(page.strips.len());
+        for strip in &page.strips {
+            offsets.push(self.pos);
+            self.put(strip)?;
+        }
+        self.put(&[0][..pad])?;
+
+        let entries = self.entries(page, page_number, offsets);
+        let ifd = self.format.ifd(self.pos, &entries)?;
+        self.pending = Some((ifd, entries.len()));
+        self.pages += 1;
+        Ok(())
+    }
+
+    /// Write the last IFD and return the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let (ifd, _) = self.pending.take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a TIFF needs at least one page",
+            )
+        })?;
+        self.put(&ifd)?;
+        self.write.flush()?;
+        Ok(self.write)
+    }
+
+    fn put(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write.write_all(data)?;
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+
+    fn entries(&self, page: &Page, page_number: u16, offsets: Vec<u64>) -> Vec<(u16, Value)> {
+        use Value::*;
+        let photometric = match page.polarity {
+            Polarity::WhiteIsZero => 0,
+            Polarity::BlackIsZero => 1,
+        };
+        let fill_order = match page.fill_order {
+            FillOrder::MsbFirst => 1,
+            FillOrder::LsbFirst => 2,
+        };
+        let byte_counts = page.strips.iter().map(|s| s.len() as u64).collect();
+
+        let mut entries = vec![
+            (254, Long(vec![2])), // NewSubfileType: page of a multi-page image
+            (256, Long(vec![page.width])),
+            (257, Long(vec![page.height])),
+            (258, Short(vec![1])), // BitsPerSample
+            (259, Short(vec![page.compression.tag_value()])),
+            (262, Short(vec![photometric])),
+            (266, Short(vec![fill_order])),
+            (274, Short(vec![1])), // Orientation: top left
+            (277, Short(vec![1])), // SamplesPerPixel
+            (282, Rational(vec![(page.x_resolution, 1)])),
+            (283, Rational(vec![(page.y_resolution, 1)])),
+            (296, Short(vec![2])), // ResolutionUnit: inch
+            (297, Short(vec![page_number, self.page_count])),
+        ];
+        match page.tile_size {
+            None => entries.extend([
+                (273, Long8(offsets)),
+                (278, Long(vec![page.rows_per_strip])),
+                (279, Long8(byte_counts)),
+            ]),
+            Some((tile_width, tile_length)) => entries.extend([
+                (322, Long(vec![tile_width])),
+                (323, Long(vec![tile_length])),
+                (324, Long8(offsets)),
+                (325, Long8(byte_counts)),
+            ]),
+        }
+        match page.compression {
+            Compression::Rle => {}
+            Compression::Group3 { t4_options } => entries.push((292, Long(vec![t4_options]))),
+            Compression::Group4 { t6_options } => entries.push((293, Long(vec![t6_options]))),
+        }
+        if let Some(ref description) = page.description {
+            entries.push((270, Ascii(description.clone())));
+        }
+        if let Some(ref software) = page.software {
+            entries.push((305, Ascii(software.clone())));
+        }
+        if let Some(ref date_time) = page.date_time {
+            entries.push((306, Ascii(date_time.clone())));
+        }
+        // IFD entries are sorted by tag
+        entries.sort_by_key(|&(tag, _)| tag);
+        entries
+    }
+}
+
+/// Read all pages of a TIFF file, in either byte order.
+///
+/// The strips of each page are copied out of `data`, use `Page::decode` to get the pixels.
+/// Returns `None` if the file is malformed or a page is not bilevel and CCITT compressed.
+/// Pages without a resolution are read as 200 dpi.
+pub fn read(data: &[u8]) -> Option<Vec<Page>> {
+    let big_endian = match data.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let mut reader = Reader {
+        data,
+        big_endian,
+        format: Format { big: false },
+    };
+    let mut pos = match reader.u16_at(2)? {
+        42 => reader.u32_at(4)? as u64,
+        // BigTIFF: size of offsets, reserved
+        43 if reader.u16_at(4)? == 8 && reader.u16_at(6)? == 0 => {
+            reader.format.big = true;
+            reader.u64_at(8)?
+        }
+        _ => return None,
+    };
+
+    let mut pages = Vec::new();
+    let mut seen = Vec::new();
+    while pos != 0 {
+        // a loop in the IFD chain
+        if seen.contains(&pos) {
+            return None;
+        }
+        seen.push(pos);
+
+        let (entries, next) = reader.ifd(pos)?;
+        pages.push(reader.page(&entries)?);
+        pos = next;
+    }
+    Some(pages)
 }
 
+/// An IFD entry, with the bytes of its value.
+struct Entry<'a> {
+    tag: u16,
+    typ: u16,
+    count: u64,
+    data: &'a [u8],
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    format: Format,
+}
+impl<'a> Reader<'a> {
+    fn bytes(&self, pos: u64, len: u64) -> Option<&'a [u8]> {
+        let start = usize::try_from(pos).ok()?;
+        let end = start.checked_add(usize::try_from(len).ok()?)?;
+        self.data.get(start..end)
+    }
+    fn u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+    fn u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+    fn u64(&self, b: &[u8]) -> u64 {
+        let b = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+        if self.big_endian {
+            u64::from_be_bytes(b)
+        } else {
+            u64::from_le_bytes(b)
+        }
+    }
+    fn u16_at(&self, pos: u64) -> Option<u16> {
+        self.bytes(pos, 2).map(|b| self.u16(b))
+    }
+    fn u32_at(&self, pos: u64) -> Option<u32> {
+        self.bytes(pos, 4).map(|b| self.u32(b))
+    }
+    fn u64_at(&self, pos: u64) -> Option<u64> {
+        self.bytes(pos, 8).map(|b| self.u64(b))
+    }
+    /// A count or offset, 32 or 64 bits depending on the format.
+    fn word(&self, b: &[u8]) -> u64 {
+        if self.format.big {
+            self.u64(b)
+        } else {
+            self.u32(b) as u64
+        }
+    }
+
+    /// The entries of the IFD at `pos` and the position of the next one.
+    fn ifd(&self, pos: u64) -> Option<(Vec<Entry<'a>>, u64)> {
+        let (count_len, entry_len, offset_len) = (
+            self.format.count_len() as u64,
+            self.format.entry_len() as u64,
+            self.format.offset_len() as u64,
+        );
+        let count = if self.format.big {
+            self.u64_at(pos)?
+        } else {
+            self.u16_at(pos)? as u64
+        };
+        let entries_end = count.checked_mul(entry_len)?.checked_add(count_len)?;
+        let ifd = self.bytes(pos, entries_end.checked_add(offset_len)?)?;
+        let (ifd, next) = ifd.split_at(entries_end as usize);
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for entry in ifd[count_len as usize..].chunks_exact(entry_len as usize) {
+            let typ = self.u16(&entry[2..]);
+            let count = self.word(&entry[4..]);
+            let value = &entry[4 + offset_len as usize..];
+            let len = type_size(typ).checked_mul(count)?;
+            let data = if len <= offset_len {
+                &value[..len as usize]
+            } else {
+                self.bytes(self.word(value), len)?
+            };
+            entries.push(Entry {
+                tag: self.u16(entry),
+                typ,
+                count,
+                data,
+            });
+        }
+        Some((entries, self.word(next)))
+    }
+
+    /// The values of an integer entry.
+    fn numbers(&self, entry: &Entry) -> Option<Vec<u64>> {
+        let data = entry.data;
+        match entry.typ {
+            1 => Some(data.iter().map(|&n| n as u64).collect()),
+            3 => Some(data.chunks_exact(2).map(|b| self.u16(b) as u64).collect()),
+            4 => Some(data.chunks_exact(4).map(|b| self.u32(b) as u64).collect()),
+            16 => Some(data.chunks_exact(8).map(|b| self.u64(b)).collect()),
+            _ => None,
+        }
+    }
+    fn number(&self, entries: &[Entry], tag: u16) -> Option<u64> {
+        let entry = entries.iter().find(|e| e.tag == tag)?;
+        self.numbers(entry)?.first().copied()
+    }
+    fn number_u32(&self, entries: &[Entry], tag: u16) -> Option<u32> {
+        u32::try_from(self.number(entries, tag)?).ok()
+    }
+    /// A resolution in dots per inch.
+    fn resolution(&self, entries: &[Entry], tag: u16) -> Option<u32> {
+        let entry = entries
+            .iter()
+            .find(|e| e.tag == tag && e.typ == 5 && e.count > 0)?;
+        let nom = self.u32(entry.data) as f64;
+        let denom = self.u32(&entry.data[4..]) as f64;
+        let per_cm = self.number(entries, 296) == Some(3);
+        let dpi = if per_cm {
+            nom / denom * 2.54
+        } else {
+            nom / denom
+        };
+        // also rejects a zero denominator
+        if dpi.is_finite() && dpi >= 1.0 {
+            Some(dpi.round() as u32)
+        } else {
+            None
+        }
+    }
+    fn ascii(&self, entries: &[Entry], tag: u16) -> Option<String> {
+        let entry = entries.iter().find(|e| e.tag == tag && e.typ == 2)?;
+        let len = entry
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(entry.data.len());
+        Some(String::from_utf8_lossy(&entry.data[..len]).into_owned())
+    }
+
+    fn page(&self, entries: &[Entry]) -> Option<Page> {
+        let width = self.number_u32(entries, 256)?;
+        let height = self.number_u32(entries, 257)?;
+        if self.number(entries, 258).unwrap_or(1) != 1 {
+            return None;
+        }
+        let compression = match self.number(entries, 259)? {
+            2 => Compression::Rle,
+            3 => Compression::Group3 {
+                t4_options: self.number_u32(entries, 292).unwrap_or(0),
+            },
+            4 => Compression::Group4 {
+                t6_options: self.number_u32(entries, 293).unwrap_or(0),
+            },
+            _ => return None,
+        };
+        let polarity = match self.number(entries, 262) {
+            Some(1) => Polarity::BlackIsZero,
+            _ => Polarity::WhiteIsZero,
+        };
+        let fill_order = match self.number(entries, 266) {
+            Some(2) => FillOrder::LsbFirst,
+            _ => FillOrder::MsbFirst,
+        };
+        // the default is 2^32 - 1, a single strip
+        let rows_per_strip = self
+            .number_u32(entries, 278)
+            .map_or(height, |rows| rows.min(height));
+
+        // TileWidth and TileLength
+        let tile_size = match (self.number(entries, 322), self.number(entries, 323)) {
+            (Some(width), Some(length)) => {
+                Some((u32::try_from(width).ok()?, u32::try_from(length).ok()?))
+            }
+            _ => None,
+        };
+        // StripOffsets and StripByteCounts, or TileOffsets and TileByteCounts
+        let (offsets, byte_counts) = match tile_size {
+            None => (273, 279),
+            Some(_) => (324, 325),
+        };
+        let offsets = self.numbers(entries.iter().find(|e| e.tag == offsets)?)?;
+        let byte_counts = self.numbers(entries.iter().find(|e| e.tag == byte_counts)?)?;
+        if offsets.len() != byte_counts.len() {
+            return None;
+        }
+        let strips = offsets
+            .iter()
+            .zip(&byte_counts)
+            .map(|(&pos, &len)| self.bytes(pos, len).map(<[u8]>::to_vec))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Page {
+            width,
+            height,
+            compression,
+            fill_order,
+            polarity,
+            x_resolution: self.resolution(entries, 282).unwrap_or(200),
+            y_resolution: self.resolution(entries, 283).unwrap_or(200),
+            date_time: self.ascii(entries, 306),
+            software: self.ascii(entries, 305),
+            description: self.ascii(entries, 270),
+            rows_per_strip,
+            strips,
+            tile_size,
+        })
+    }
+}
+
+/// Size in bytes of a value of the given type, `0` for unknown types.
+fn type_size(typ: u16) -> u64 {
+    match typ {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 | 16 | 17 | 18 => 8,
+        _ => 0,
+    }
+}
+
+/// Wrap a Group 4 encoded image into a single page TIFF.
 pub fn wrap(data: &[u8], width: u32, height: u32) -> Vec<u8> {
-    use Value::*;
-    let header_data = [
-        (256, Long(width)),             // ImageWidth
-        (257, Long(height)),            // ImageLength
-        (259, Short(4)),                // Compression
-        (262, Short(0)),                // PhotometricInterpretation
-        (273, DataOffset),              // StripOffsets
-        (274, Short(1)),                // Orientation
-        (278, Long(height)),            // RowsPerStrip
-        (279, Long(data.len() as u32)), // StripByteCounts
-        (282, Rational(200, 1)),        // XResolution
-        (283, Rational(200, 1)),        // YResolution
-        (296, Short(2)),                // ResolutionUnit
-    ];
-    let rat_data_len = 2 * 8; // number of rationals * 8
-    let ifd_end = 4 + // magic
-        4 + // IFD offset
-        2 + // IFD entry count
-        12 * header_data.len() + // IFD enties
-        4; // null pointer at end of IFD
-    let header_size = ifd_end + rat_data_len;
-
-    let mut out = Vec::with_capacity(header_size + data.len());
-
-    out.extend_from_slice(&[73, 73, 42, 0]);
-    let ifd_offset: u32 = 8;
-    out.extend_from_slice(&ifd_offset.to_le_bytes());
-
-    out.extend_from_slice(&u16::to_le_bytes(header_data.len() as u16));
-
-    let mut num_rat = 0;
-    for &(tag, val) in header_data.iter() {
-        let (typ_num, val) = match val {
-            Short(n) => (3, n as u32),
-            Long(n) => (4, n),
-            Rational(_, _) => {
-                let o = ifd_end + 8 * num_rat;
-                num_rat += 1;
-                (5, o as u32)
-            }
-            DataOffset => (4, header_size as u32),
+    let page = Page::new(
+        width,
+        height,
+        Compression::Group4 { t6_options: 0 },
+        data.to_vec(),
+    );
+    let mut writer = TiffWriter::new(Vec::new()).with_page_count(1);
+    writer.write_page(&page).unwrap();
+    writer.finish().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{Encoder, Group3Encoder, RleEncoder};
+    use crate::VecWriter;
+
+    fn bitmap(width: u32, height: u32) -> Bitmap {
+        let mut bitmap = Bitmap::new(width, height);
+        for y in 0..height {
+            bitmap.set_transitions(y, &[y % width, (2 * y + 3) % width, width - 1]);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn multi_page() {
+        let mut first = Page::new(
+            10,
+            3,
+            Compression::Group3 { t4_options: T4_2D },
+            vec![1, 2, 3],
+        );
+        first.fill_order = FillOrder::LsbFirst;
+        first.x_resolution = 204;
+        first.y_resolution = 98;
+        first.software = Some("fax".into());
+        first.date_time = Some("2024:01:02 03:04:05".into());
+        first.description = Some("first page".into());
+        let second = Page::new(20, 5, Compression::Rle, vec![4; 8]);
+
+        let mut writer = TiffWriter::new(Vec::new()).with_page_count(2);
+        writer.write_page(&first).unwrap();
+        writer.write_page(&second).unwrap();
+        let data = writer.finish().unwrap();
+
+        assert_eq!(data[..4], *b"II*\0");
+        assert_eq!(data[8..11], [1, 2, 3]);
+        assert_eq!(read(&data), Some(vec![first, second]));
+
+        // truncated
+        assert_eq!(read(&data[..data.len() - 1]), None);
+    }
+
+    /// Every compression, with several strips, inverted and in LSB-first fill order.
+    #[test]
+    fn decode_pages() {
+        let (width, height) = (37, 9);
+        let bitmap = bitmap(width, height);
+        let strip = |rows: std::ops::Range<u32>, compression: Compression| -> Vec<u8> {
+            let pels = |y| bitmap.pels(y);
+            match compression {
+                Compression::Rle => {
+                    let mut encoder = RleEncoder::new(VecWriter::new());
+                    for y in rows {
+                        encoder.encode_line(pels(y), width).unwrap();
+                    }
+                    encoder.finish().unwrap().finish()
+                }
+                Compression::Group3 { .. } => {
+                    let mut encoder = Group3Encoder::new_2d(VecWriter::new(), 2);
+                    for y in rows {
+                        encoder.encode_line(pels(y), width).unwrap();
+                    }
+                    encoder.finish().unwrap().finish()
+                }
+                Compression::Group4 { .. } => {
+                    let mut encoder = Encoder::new(VecWriter::new());
+                    for y in rows {
+                        encoder.encode_line(pels(y), width).unwrap();
+                    }
+                    encoder.finish().unwrap().finish()
+                }
+            }
+        };
+
+        let mut writer = TiffWriter::new(Vec::new());
+        let mut expected = vec![];
+        for compression in [
+            Compression::Rle,
+            Compression::Group3 { t4_options: T4_2D },
+            Compression::Group4 { t6_options: 0 },
+        ] {
+            let mut page = Page::new(width, height, compression, strip(0..height, compression));
+            writer.write_page(&page).unwrap();
+            expected.push(bitmap.clone());
+
+            page.rows_per_strip = 4;
+            page.strips = vec![
+                strip(0..4, compression),
+                strip(4..8, compression),
+                strip(8..9, compression),
+            ];
+            writer.write_page(&page).unwrap();
+            expected.push(bitmap.clone());
+
+            page.fill_order = FillOrder::LsbFirst;
+            page.polarity = Polarity::BlackIsZero;
+            for strip in &mut page.strips {
+                strip.iter_mut().for_each(|b| *b = b.reverse_bits());
+            }
+            writer.write_page(&page).unwrap();
+            let mut inverted = bitmap.clone();
+            for y in 0..height {
+                inverted.set_pels(y, bitmap.pels(y).map(|c| !c));
+            }
+            expected.push(inverted);
+        }
+        let data = writer.finish().unwrap();
+
+        let pages = read(&data).unwrap();
+        let decoded: Vec<_> = pages.iter().map(|p| p.decode().unwrap()).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn encode_strips() {
+        let (width, height) = (37, 9);
+        let bitmap = bitmap(width, height);
+        let mut writer = TiffWriter::new(Vec::new());
+        let mut pages = vec![];
+        for compression in [
+            Compression::Rle,
+            Compression::Group3 { t4_options: 0 },
+            Compression::Group3 {
+                t4_options: T4_2D | T4_FILL_BITS,
+            },
+            Compression::Group4 { t6_options: 0 },
+        ] {
+            let page = Page::encode(&bitmap, compression, 4);
+            assert_eq!(page.rows_per_strip, 4);
+            assert_eq!(page.strips.len(), 3);
+            assert_eq!(page.decode(), Some(bitmap.clone()));
+
+            // every strip decodes on its own
+            let mut last = page.clone();
+            last.height = 1;
+            last.strips = vec![page.strips[2].clone()];
+            let mut expected = Bitmap::new(width, 1);
+            expected.row_mut(0).copy_from_slice(bitmap.row(8));
+            assert_eq!(last.decode(), Some(expected));
+
+            writer.write_page(&page).unwrap();
+            pages.push(page);
+        }
+        assert_eq!(
+            pages[2].compression,
+            Compression::Group3 { t4_options: T4_2D }
+        );
+        let data = writer.finish().unwrap();
+        assert_eq!(read(&data), Some(pages));
+
+        let page = Page::encode(&bitmap, Compression::Group4 { t6_options: 0 }, 100);
+        assert_eq!(page.strips.len(), 1);
+        assert_eq!(page.rows_per_strip, height);
+    }
+
+    #[test]
+    fn tiles() {
+        let (width, height) = (70, 37);
+        let bitmap = bitmap(width, height);
+        let page = Page::g4_tiles(&bitmap, 32, 16).unwrap();
+        assert_eq!(Page::g4_tiles(&bitmap, 24, 16), None);
+        // 3 tiles across, 3 down
+        assert_eq!(page.strips.len(), 9);
+
+        let mut writer = TiffWriter::new(Vec::new());
+        writer.write_page(&page).unwrap();
+        let mut invalid = page.clone();
+        invalid.strips.pop();
+        assert!(writer.write_page(&invalid).is_err());
+        invalid.tile_size = Some((24, 16));
+        assert!(writer.write_page(&invalid).is_err());
+        let data = writer.finish().unwrap();
+
+        let pages = read(&data).unwrap();
+        assert_eq!(pages, [page]);
+        assert_eq!(pages[0].decode(), Some(bitmap));
+
+        // missing tiles, bad tile sizes
+        let mut invalid = pages[0].clone();
+        invalid.strips.pop();
+        assert_eq!(invalid.decode(), None);
+        let mut invalid = pages[0].clone();
+        invalid.tile_size = Some((24, 16));
+        assert_eq!(invalid.decode(), None);
+        invalid.width = 0x8000_0001;
+        invalid.height = 1;
+        invalid.tile_size = Some((0x8000_0000, 16));
+        assert_eq!(invalid.decode(), None);
+    }
+
+    #[test]
+    fn page_tags() {
+        let data = wrap(&[0; 4], 16, 2);
+        let reader = Reader {
+            data: &data,
+            big_endian: false,
+            format: Format { big: false },
+        };
+        let (entries, _) = reader.ifd(reader.u32_at(4).unwrap() as u64).unwrap();
+        assert_eq!(reader.number(&entries, 274), Some(1));
+        assert_eq!(entries.iter().find(|e| e.tag == 279).unwrap().typ, 4);
+        let page_number = entries.iter().find(|e| e.tag == 297).unwrap();
+        assert_eq!(reader.numbers(page_number), Some(vec![0, 1]));
+
+        // PageNumber is a SHORT
+        let page = Page::new(16, 2, Compression::Rle, vec![0; 4]);
+        let mut writer = TiffWriter::new(Vec::new());
+        writer.pages = u16::MAX as u32;
+        writer.write_page(&page).unwrap();
+        assert!(writer.write_page(&page).is_err());
+    }
+
+    #[test]
+    fn big_tiff() {
+        let mut first = Page::new(37, 9, Compression::Group4 { t6_options: 0 }, vec![]);
+        let mut encoder = Encoder::new(VecWriter::new());
+        encoder.encode_bitmap(&bitmap(37, 9)).unwrap();
+        first.strips = vec![encoder.finish().unwrap().finish()];
+        first.description = Some("BigTIFF".into());
+        let second = Page::new(20, 5, Compression::Rle, vec![4; 8]);
+
+        let mut writer = TiffWriter::new(Vec::new()).with_big_tiff(true);
+        writer.write_page(&first).unwrap();
+        writer.write_page(&second).unwrap();
+        let data = writer.finish().unwrap();
+
+        assert_eq!(data[..8], *b"II+\0\x08\0\0\0");
+        let reader = Reader {
+            data: &data,
+            big_endian: false,
+            format: Format { big: true },
         };
-        let count = 1;
-        out.extend_from_slice(&u16::to_le_bytes(tag));
-        out.extend_from_slice(&u16::to_le_bytes(typ_num));
-        out.extend_from_slice(&u32::to_le_bytes(count));
-        out.extend_from_slice(&u32::to_le_bytes(val));
+        let (entries, _) = reader.ifd(reader.u64_at(8).unwrap()).unwrap();
+        // StripOffsets and StripByteCounts are LONG8
+        for tag in [273, 279] {
+            assert_eq!(entries.iter().find(|e| e.tag == tag).unwrap().typ, 16);
+        }
+        let pages = read(&data).unwrap();
+        assert_eq!(pages[0].decode(), Some(bitmap(37, 9)));
+        assert_eq!(pages, [first, second]);
     }
-    // NULL at IFD end
-    out.extend_from_slice(&[0; 4]);
 
-    // write additional data
-    for &(_, val) in header_data.iter() {
-        if let Value::Rational(nom, denom) = val {
-            out.extend_from_slice(&nom.to_le_bytes());
-            out.extend_from_slice(&denom.to_le_bytes());
+    #[test]
+    fn malformed() {
+        // ImageWidth of the unknown type 13
+        let mut data = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        data.extend_from_slice(&[0, 1, 13, 0, 1, 0, 0, 0, 10, 0, 0, 0]);
+        data.extend_from_slice(&[0; 4]);
+        assert_eq!(read(&data), None);
+
+        // BigTIFF IFD with a huge number of entries
+        let mut data = b"II+\0\x08\0\0\0\x10\0\0\0\0\0\0\0".to_vec();
+        data.extend_from_slice(&(u64::MAX / 20).to_le_bytes());
+        assert_eq!(read(&data), None);
+
+        // bad header, offset past the end, IFD pointing to itself
+        assert_eq!(read(b"XX*\0\x08\0\0\0"), None);
+        assert_eq!(read(b"II*\0\xff\0\0\0"), None);
+        let page = Page::new(8, 1, Compression::Group4 { t6_options: 0 }, vec![0; 2]);
+        let mut writer = TiffWriter::new(Vec::new());
+        writer.write_page(&page).unwrap();
+        let mut data = writer.finish().unwrap();
+        let ifd = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let count = u16::from_le_bytes([data[ifd], data[ifd + 1]]) as usize;
+        let next = ifd + 2 + 12 * count;
+        data[next..next + 4].copy_from_slice(&(ifd as u32).to_le_bytes());
+        assert_eq!(read(&data), None);
+
+        // a page far larger than its data
+        for (width, height) in [(1 << 31, 1 << 31), (1 << 31, 100), (100, 1 << 20)] {
+            let pages = read(&wrap(&[0; 16], width, height)).unwrap();
+            assert_eq!(pages[0].decode(), None);
         }
     }
 
-    assert_eq!(out.len(), header_size);
-    out.extend_from_slice(data);
-    out
+    #[test]
+    fn big_endian() {
+        let (width, height) = (30, 4);
+        let bitmap = bitmap(width, height);
+        let mut encoder = Encoder::new(VecWriter::new());
+        encoder.encode_bitmap(&bitmap).unwrap();
+        let strip = encoder.finish().unwrap().finish();
+
+        let entries: [(u16, u16, u32); 7] = [
+            (256, 3, width),
+            (257, 4, height),
+            (259, 3, 4),
+            (262, 3, 0),
+            (273, 4, 8 + 2 + 7 * 12 + 4),
+            (278, 3, height),
+            (279, 4, strip.len() as u32),
+        ];
+        let mut data = b"MM\0\x2a\0\0\0\x08".to_vec();
+        data.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+        for (tag, typ, value) in entries {
+            data.extend_from_slice(&tag.to_be_bytes());
+            data.extend_from_slice(&typ.to_be_bytes());
+            data.extend_from_slice(&1u32.to_be_bytes());
+            // short values are left aligned
+            match typ {
+                3 => data.extend_from_slice(&[(value as u16).to_be_bytes(), [0, 0]].concat()),
+                _ => data.extend_from_slice(&value.to_be_bytes()),
+            }
+        }
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&strip);
+
+        let pages = read(&data).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].compression, Compression::Group4 { t6_options: 0 });
+        assert_eq!(pages[0].x_resolution, 200);
+        assert_eq!(pages[0].decode(), Some(bitmap));
+    }
 }
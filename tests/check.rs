@@ -1,5 +1,5 @@
-use fax::{decoder, decoder::pels, BitWriter, Bits, Color, VecWriter};
-use fax::{encoder, slice_bits, slice_reader, BitReader, ByteReader};
+use fax::{decoder, decoder::pack_row, BitWriter, Bits, Polarity};
+use fax::{encoder, slice_pels, slice_reader, tiff, BitReader, ByteReader};
 use std::fmt::Debug;
 use std::fs;
 use std::path::Path;
@@ -37,7 +37,7 @@ fn decode_test_images() {
         let result = if p.extension().is_some_and(|e| e == "fax") {
             let img = read_pbm(&pbm);
             let data = fs::read(&p).unwrap();
-            img.test_decode(&data, false)
+            img.test_decode(&data, Polarity::WhiteIsZero)
         } else if p.extension().is_some_and(|e| e == "tiff") {
             let img = read_pbm(&pbm);
             img.test_decode_tiff(&p)
@@ -103,7 +103,7 @@ fn roundtrip_encode_test_images() {
             continue;
         };
         let img = read_pbm(&pbm);
-        if let Err(line) = img.test_encode(&data, false) {
+        if let Err(line) = img.test_encode(&data, Polarity::WhiteIsZero) {
             unexpected_failures.push(format!("{}: failed at line {line}", p.display()));
         }
     }
@@ -137,30 +137,15 @@ fn read_pbm(path: &Path) -> TestImage {
     }
 }
 fn read_tiff_stream(path: &Path) -> Option<Vec<u8>> {
-    use tiff::{decoder::Decoder, tags::Tag};
-    let data = fs::read(path).unwrap();
-    let reader = std::io::Cursor::new(data.as_slice());
-    let mut decoder = Decoder::new(reader).unwrap();
-    let strip_offset = decoder
-        .get_tag(Tag::StripOffsets)
-        .unwrap()
-        .into_u32()
-        .unwrap() as usize;
-    let strip_bytes = decoder
-        .get_tag(Tag::StripByteCounts)
-        .unwrap()
-        .into_u32()
-        .unwrap() as usize;
-    Some(data[strip_offset..strip_offset + strip_bytes].to_vec())
+    let mut pages = tiff::read(&fs::read(path).unwrap()).unwrap();
+    match pages[0].strips.len() {
+        1 => pages[0].strips.pop(),
+        _ => None,
+    }
 }
 
 impl TestImage {
-    fn test_decode(&self, data: &[u8], white_is_1: bool) -> Result<(), String> {
-        let (black, white) = match white_is_1 {
-            false => (Bits { data: 1, len: 1 }, Bits { data: 0, len: 1 }),
-            true => (Bits { data: 0, len: 1 }, Bits { data: 1, len: 1 }),
-        };
-
+    fn test_decode(&self, data: &[u8], polarity: Polarity) -> Result<(), String> {
         let ref_lines: Vec<&[u8]> = self
             .data
             .chunks_exact((self.width as usize + 7) / 8)
@@ -169,16 +154,9 @@ impl TestImage {
 
         let mut decoded_lines = vec![];
         let ok = decoder::decode_g4(data.iter().cloned(), self.width, None, |transitions| {
-            let mut writer = VecWriter::new();
-            for c in pels(transitions, self.width) {
-                let bit = match c {
-                    Color::Black => black,
-                    Color::White => white,
-                };
-                writer.write(bit).unwrap();
-            }
-            writer.pad();
-            decoded_lines.push(writer.finish());
+            let mut line = vec![0; (self.width as usize + 7) / 8];
+            pack_row(transitions, self.width, polarity, &mut line);
+            decoded_lines.push(line);
         });
 
         if ok.is_none() {
@@ -200,42 +178,30 @@ impl TestImage {
     }
 
     fn test_decode_tiff(&self, path: &Path) -> Result<(), String> {
-        use tiff::{decoder::Decoder, tags::Tag};
-        let data = fs::read(path).unwrap();
-        let reader = std::io::Cursor::new(data.as_slice());
-        let mut decoder = Decoder::new(reader).unwrap();
-        let strip_offset = decoder
-            .get_tag(Tag::StripOffsets)
-            .unwrap()
-            .into_u32()
-            .unwrap() as usize;
-        let strip_bytes = decoder
-            .get_tag(Tag::StripByteCounts)
-            .unwrap()
-            .into_u32()
-            .unwrap() as usize;
-
-        let white_is_1 = decoder
-            .get_tag(Tag::PhotometricInterpretation)
-            .unwrap()
-            .into_u16()
-            .unwrap()
-            != 0;
-
-        let stream = &data[strip_offset..strip_offset + strip_bytes];
-        self.test_decode(stream, white_is_1)
+        let pages = tiff::read(&fs::read(path).unwrap()).ok_or("TIFF read failed")?;
+        let bitmap = pages[0].decode().ok_or("TIFF decode failed")?;
+        if (bitmap.width(), bitmap.height()) != (self.width, self.height) {
+            return Err(format!(
+                "page is {}x{}, expected {}x{}",
+                bitmap.width(),
+                bitmap.height(),
+                self.width,
+                self.height
+            ));
+        }
+        for (i, (decoded, expected)) in bitmap
+            .rows()
+            .zip(self.data.chunks_exact(bitmap.stride()))
+            .enumerate()
+        {
+            if decoded != expected {
+                return Err(format!("line {i} pixel mismatch"));
+            }
+        }
+        Ok(())
     }
 
-    fn test_encode(&self, data: &[u8], white_is_1: bool) -> Result<(), usize> {
-        fn pixels(line: &[u8], white_is_1: bool) -> impl Iterator<Item = Color> + '_ {
-            slice_bits(line).map(move |b| {
-                if b ^ white_is_1 {
-                    Color::Black
-                } else {
-                    Color::White
-                }
-            })
-        }
+    fn test_encode(&self, data: &[u8], polarity: Polarity) -> Result<(), usize> {
         let mut expected = slice_reader(data);
         let mut encoder = encoder::Encoder::new(TestWriter {
             expected: &mut expected,
@@ -247,7 +213,7 @@ impl TestImage {
             .take(self.height as usize);
         for (i, line) in ref_lines.enumerate() {
             if encoder
-                .encode_line(pixels(line, white_is_1), self.width)
+                .encode_line(slice_pels(line, self.width, polarity), self.width)
                 .is_err()
             {
                 return Err(i);
@@ -1,4 +1,4 @@
-use fax::decoder;
+use fax::decoder::{self, Concealment};
 use fax::tiff::wrap;
 use std::fs;
 use std::path::Path;
@@ -41,3 +41,56 @@ fn main() {
         }
     }
 }
+
+/// Truncated samples still produce a full-height image.
+#[test]
+fn best_effort() {
+    let dir = Path::new("test-files/errors");
+    let height = 4000;
+
+    for entry in fs::read_dir(dir).expect("Failed to read directory") {
+        let path = entry.expect("Failed to read entry").path();
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        if let Some((_, width)) = parse_filename(&name) {
+            let data = fs::read(&path).expect("Failed to read input file");
+            let truncated = &data[..data.len() / 2];
+
+            let mut decoded = vec![];
+            for conceal in [Concealment::Repeat, Concealment::White] {
+                let mut lines = vec![];
+                let broken_at = decoder::decode_g4_best_effort(
+                    truncated.iter().cloned(),
+                    width,
+                    height,
+                    conceal,
+                    |t| {
+                        assert!(
+                            t.iter().all(|&x| x < width),
+                            "{}: transition out of range",
+                            name
+                        );
+                        lines.push(t.to_vec());
+                    },
+                );
+                assert_eq!(lines.len(), height as usize, "{}", name);
+
+                // the second half of the data is missing
+                let broken_at = broken_at.expect(&name) as usize;
+                assert!(broken_at > 0 && broken_at < lines.len(), "{}", name);
+                let fill = match conceal {
+                    Concealment::Repeat => lines[broken_at - 1].clone(),
+                    Concealment::White => vec![],
+                };
+                let concealed = lines[broken_at..].iter().filter(|&l| *l == fill).count();
+                assert_eq!(concealed, lines.len() - broken_at, "{}", name);
+                decoded.push((broken_at, lines));
+            }
+            // both only differ in the concealed lines
+            let (broken_at, ref repeat) = decoded[0];
+            let (_, ref white) = decoded[1];
+            assert_eq!(decoded[1].0, broken_at, "{}", name);
+            assert_eq!(repeat[..broken_at], white[..broken_at], "{}", name);
+        }
+    }
+}